@@ -0,0 +1,6 @@
+-- Admin users allowed to access privileged endpoints
+CREATE TABLE users(
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
@@ -0,0 +1,6 @@
+-- Server-side storage for admin login sessions
+CREATE TABLE sessions(
+    id TEXT PRIMARY KEY,
+    data JSONB NOT NULL,
+    expiry_date timestamptz NOT NULL
+);
@@ -0,0 +1,7 @@
+-- Tokens expire and can only be used once.
+-- Tokens issued before this migration get a fresh 24 hours to be used.
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours',
+    ADD COLUMN consumed_at timestamptz NULL;
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,6 @@
+-- Tokens are stored as HMAC-SHA256 hashes from now on.
+-- The secret is only known to the application: rows created before this migration
+-- still hold a plaintext token and are flagged so that the application hashes them on startup.
+ALTER TABLE subscription_tokens RENAME COLUMN subscription_token TO subscription_token_hash;
+ALTER TABLE subscription_tokens ADD COLUMN is_plaintext BOOLEAN NOT NULL DEFAULT true;
+ALTER TABLE subscription_tokens ALTER COLUMN is_plaintext SET DEFAULT false;
@@ -0,0 +1,7 @@
+-- Proof-of-work challenges are stateless until they are used:
+-- remembering the redeemed ones is enough to stop a solution from being replayed.
+CREATE TABLE proof_of_work_redemptions (
+    challenge_id uuid PRIMARY KEY,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX proof_of_work_redemptions_expires_at_idx ON proof_of_work_redemptions (expires_at);
@@ -0,0 +1,2 @@
+-- Backs the keyset pagination of the admin subscriber listing.
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
@@ -0,0 +1,5 @@
+-- When and how a subscriber agreed to receive the newsletter:
+-- `double_opt_in` when they clicked the confirmation link, `import` when an admin vouched for it.
+-- Unknown for subscribers confirmed before we started keeping track.
+ALTER TABLE subscriptions ADD COLUMN consented_at timestamptz NULL;
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
@@ -0,0 +1,17 @@
+-- Delivery problems reported by the email provider, kept for auditing.
+-- `(provider, provider_event_id)` makes webhook retries idempotent.
+CREATE TABLE email_events (
+    id uuid PRIMARY KEY,
+    provider TEXT NOT NULL,
+    provider_event_id TEXT NOT NULL,
+    -- e.g. `Bounce` or `SpamComplaint`
+    record_type TEXT NOT NULL,
+    -- e.g. `HardBounce` or `SoftBounce`
+    bounce_type TEXT NULL,
+    email TEXT NOT NULL,
+    subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE SET NULL,
+    occurred_at timestamptz NULL,
+    received_at timestamptz NOT NULL,
+    payload jsonb NOT NULL,
+    UNIQUE (provider, provider_event_id)
+);
@@ -0,0 +1,10 @@
+-- Addresses we must never mail again, whether or not they are (still) subscribed.
+-- Emails are stored lowercased, so a different spelling does not get around the list.
+CREATE TABLE suppressions (
+    email TEXT PRIMARY KEY,
+    -- `hard_bounce`, `spam_complaint`, `erasure_request` or `manual`
+    reason TEXT NOT NULL,
+    -- Who added it, e.g. `postmark` or `admin`
+    source TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
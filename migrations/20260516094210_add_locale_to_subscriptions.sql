@@ -0,0 +1,3 @@
+-- The language to write to the subscriber in, e.g. `fr`, picked at signup among those we have
+-- a message catalog for. `NULL` for subscribers from before we asked: they get the default one.
+ALTER TABLE subscriptions ADD COLUMN locale TEXT NULL;
@@ -0,0 +1,3 @@
+-- The Markdown source of issues written in Markdown, which `text_content` and `html_content`
+-- are rendered from. `NULL` for issues published with both formats written by hand.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
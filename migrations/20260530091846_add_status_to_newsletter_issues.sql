@@ -0,0 +1,14 @@
+-- Issues are written as drafts before they go out. Those published so far went out straight away.
+-- `draft`, `scheduled` or `sent`
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NULL;
+UPDATE newsletter_issues SET status = 'sent';
+ALTER TABLE newsletter_issues ALTER COLUMN status SET NOT NULL;
+
+ALTER TABLE newsletter_issues ADD COLUMN created_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN updated_at timestamptz NULL;
+UPDATE newsletter_issues SET created_at = published_at, updated_at = published_at;
+ALTER TABLE newsletter_issues ALTER COLUMN created_at SET NOT NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN updated_at SET NOT NULL;
+
+-- Only set once the issue is sent
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
@@ -0,0 +1,5 @@
+-- When a `scheduled` issue is due: the scheduler sends it once this is in the past.
+ALTER TABLE newsletter_issues ADD COLUMN scheduled_at timestamptz NULL;
+-- What the scheduler looks for, over and over again
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (scheduled_at)
+    WHERE status = 'scheduled';
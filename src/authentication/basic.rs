@@ -0,0 +1,107 @@
+use crate::authentication::{AuthError, Credentials, validate_credentials};
+use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::{
+    extract::FromRequestParts,
+    http::{HeaderMap, HeaderValue, StatusCode, header, request::Parts},
+    response::{IntoResponse, Response},
+};
+use base64::Engine;
+use secrecy::SecretString;
+use uuid::Uuid;
+
+/// Extractor for handlers that require HTTP Basic authentication.
+///
+/// Requests without valid credentials are rejected with a `401 Unauthorized`
+/// and a `WWW-Authenticate` challenge before the handler runs.
+#[derive(Debug)]
+pub struct BasicAuthUser {
+    pub user_id: Uuid,
+}
+
+impl FromRequestParts<AppState> for BasicAuthUser {
+    type Rejection = BasicAuthError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let credentials =
+            basic_authentication(&parts.headers).map_err(BasicAuthError::AuthError)?;
+        let user_id = validate_credentials(credentials, &state.db)
+            .await
+            .map_err(|e| match e {
+                AuthError::InvalidCredentials(_) => BasicAuthError::AuthError(e.into()),
+                AuthError::UnexpectedError(_) => BasicAuthError::UnexpectedError(e.into()),
+            })?;
+        Ok(Self { user_id })
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum BasicAuthError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for BasicAuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for BasicAuthError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::AuthError(_) => {
+                tracing::warn!(error = ?self, "Rejected an unauthenticated request");
+                let mut response = StatusCode::UNAUTHORIZED.into_response();
+                let header_value = HeaderValue::from_static(r#"Basic realm="publish""#);
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to authenticate a request");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+pub(crate) fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    // The header value, if present, must be a valid UTF8 string
+    let header_value = headers
+        .get(header::AUTHORIZATION)
+        .context("The 'Authorization' header was missing")?
+        .to_str()
+        .context("The 'Authorization' header was not a valid UTF8 string.")?;
+    let base64encoded_segment = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme was not 'Basic'.")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64encoded_segment)
+        .context("Failed to base64-decode 'Basic' credentials.")?;
+    let decoded_credentials = String::from_utf8(decoded_bytes)
+        .context("The decoded credential string is not valid UTF8.")?;
+
+    // Split into two segments, using ':' as delimiter
+    let mut credentials = decoded_credentials.splitn(2, ':');
+    let username = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A username must be provided in 'Basic' auth."))?
+        .to_string();
+    let password = credentials
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("A password must be provided in 'Basic' auth."))?
+        .to_string();
+
+    Ok(Credentials {
+        username,
+        password: SecretString::from(password),
+    })
+}
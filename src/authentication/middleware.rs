@@ -0,0 +1,46 @@
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use axum::{
+    extract::Request,
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
+};
+use std::ops::Deref;
+use uuid::Uuid;
+
+/// Id of the logged-in admin, made available to handlers as a request extension
+/// by [`reject_anonymous_users`].
+#[derive(Copy, Clone, Debug)]
+pub struct UserId(Uuid);
+
+impl std::fmt::Display for UserId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Deref for UserId {
+    type Target = Uuid;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Redirect requests without a logged-in session to the login form.
+pub async fn reject_anonymous_users(
+    session: TypedSession,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, Response> {
+    match session.get_user_id().await.map_err(e500)? {
+        Some(user_id) => {
+            request.extensions_mut().insert(UserId(user_id));
+            Ok(next.run(request).await)
+        }
+        None => {
+            tracing::info!("Redirecting an anonymous user to the login form");
+            Ok(Redirect::to("/login").into_response())
+        }
+    }
+}
@@ -0,0 +1,8 @@
+mod basic;
+mod middleware;
+mod password;
+
+pub(crate) use basic::basic_authentication;
+pub use basic::{BasicAuthError, BasicAuthUser};
+pub use middleware::{UserId, reject_anonymous_users};
+pub use password::{AuthError, Credentials, compute_password_hash, validate_credentials};
@@ -8,12 +8,31 @@ use url::Url;
 use validator::ValidationErrors;
 
 use crate::domain::SubscriberEmail;
+use crate::email_client::{
+    EmailClient, EmailSender, FileOutboxSender, PostmarkSender, RetryPolicy, SmtpSender, SmtpTls,
+    SuppressionList,
+};
+use crate::proof_of_work::ProofOfWork;
+use crate::templates::EmailTemplates;
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(serde::Deserialize, Clone)]
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub rate_limit: RateLimitSettings,
+    pub proof_of_work: ProofOfWorkSettings,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    #[serde(default)]
+    pub email_templates: EmailTemplateSettings,
+    #[serde(default)]
+    pub newsletter_issues: NewsletterIssueSettings,
+    /// Only used to bootstrap a fresh database, see [`InitialAdminSettings`].
+    #[serde(default)]
+    pub initial_admin: Option<InitialAdminSettings>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -51,10 +70,61 @@ impl DatabaseSettings {
 
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub provider: EmailProvider,
+    /// Postmark only
     pub base_url: String,
     pub sender_email: String,
+    /// Postmark only
     pub auth_token: SecretString,
     pub timeout_milliseconds: u64,
+    pub retry: RetrySettings,
+    /// Required when `provider` is `smtp`
+    pub smtp: Option<SmtpSettings>,
+    /// Required when `provider` is `file`
+    pub outbox_directory: Option<PathBuf>,
+}
+
+/// Which backend `EmailClient` hands emails over to.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Smtp,
+    /// Write emails to `outbox_directory` instead of sending them. For local development.
+    File,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    pub password: Option<SecretString>,
+}
+
+/// How `EmailClient` retries transient delivery failures.
+#[derive(serde::Deserialize, Clone)]
+pub struct RetrySettings {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub jitter: bool,
+}
+
+impl RetrySettings {
+    pub fn policy(&self) -> RetryPolicy {
+        RetryPolicy::new(
+            self.max_attempts,
+            std::time::Duration::from_millis(self.base_delay_milliseconds),
+            std::time::Duration::from_millis(self.max_delay_milliseconds),
+            self.jitter,
+        )
+    }
 }
 
 impl EmailClientSettings {
@@ -65,6 +135,33 @@ impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
+
+    pub fn client(self, suppressions: Arc<dyn SuppressionList>) -> EmailClient {
+        let sender_email = self.sender().expect("Invalid sender email address.");
+        let timeout = self.timeout();
+        let retry_policy = self.retry.policy();
+        let provider: Arc<dyn EmailSender> = match self.provider {
+            EmailProvider::Postmark => {
+                Arc::new(PostmarkSender::new(self.base_url, self.auth_token, timeout))
+            }
+            EmailProvider::Smtp => {
+                let smtp = self
+                    .smtp
+                    .expect("Missing `email_client.smtp` settings for the SMTP provider.");
+                let credentials = smtp.username.zip(smtp.password);
+                Arc::new(
+                    SmtpSender::new(&smtp.host, smtp.port, smtp.tls, credentials, timeout)
+                        .expect("Invalid SMTP settings."),
+                )
+            }
+            EmailProvider::File => {
+                Arc::new(FileOutboxSender::new(self.outbox_directory.expect(
+                    "Missing `email_client.outbox_directory` setting for the file provider.",
+                )))
+            }
+        };
+        EmailClient::new(sender_email, provider, retry_policy, suppressions)
+    }
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -73,6 +170,127 @@ pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
     pub base_url: Url,
+    /// Key used to sign links we hand out, e.g. to unsubscribe
+    pub hmac_secret: SecretString,
+}
+
+/// The first admin account, created at startup when there is no user at all.
+///
+/// Once it exists these settings are ignored, even if they change.
+#[derive(serde::Deserialize, Clone)]
+pub struct InitialAdminSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// Limits on `POST /subscriptions`, both per client IP and per target email address.
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    /// Reverse proxies whose `X-Forwarded-For` header we believe.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    pub per_ip: TokenBucketSettings,
+    pub per_email: TokenBucketSettings,
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct TokenBucketSettings {
+    /// How many requests can be made in a burst.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub capacity: u32,
+    /// How long it takes to earn back one request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub refill_interval_milliseconds: u64,
+}
+
+/// Optional proof-of-work challenge on `POST /subscriptions`, to slow down signup bots.
+#[derive(serde::Deserialize, Clone)]
+pub struct ProofOfWorkSettings {
+    pub enabled: bool,
+    /// How many leading zero bits the solution hash must have.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub difficulty: u8,
+    /// How long a challenge can be solved and used for.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub ttl_seconds: i64,
+}
+
+impl ProofOfWorkSettings {
+    /// `None` when the challenge is disabled.
+    pub fn proof_of_work(&self, hmac_secret: SecretString) -> Option<ProofOfWork> {
+        self.enabled.then(|| {
+            ProofOfWork::new(
+                hmac_secret,
+                self.difficulty,
+                chrono::TimeDelta::seconds(self.ttl_seconds),
+            )
+        })
+    }
+}
+
+/// HTTP Basic credentials Postmark must present when calling our webhook.
+#[derive(serde::Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    pub username: String,
+    pub password: SecretString,
+}
+
+/// Where a deployment keeps its own email templates, if it has any.
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailTemplateSettings {
+    /// Files in here replace the built-in template of the same name, e.g. `confirmation.html`,
+    /// and its `locales` folder the message catalog of the same name, e.g. `fr.yaml`.
+    pub directory: Option<PathBuf>,
+    /// For subscribers whose language we have no catalog for.
+    pub default_locale: String,
+}
+
+impl Default for EmailTemplateSettings {
+    fn default() -> Self {
+        Self {
+            directory: None,
+            default_locale: "en".into(),
+        }
+    }
+}
+
+impl EmailTemplateSettings {
+    pub fn templates(&self) -> Result<EmailTemplates, anyhow::Error> {
+        EmailTemplates::load(self.directory.as_deref(), &self.default_locale)
+    }
+}
+
+/// Settings for writing issues in the admin area, and sending them later on.
+#[derive(serde::Deserialize, Clone)]
+pub struct NewsletterIssueSettings {
+    /// The admin addresses test sends of a draft can go to: nobody else gets an unfinished issue.
+    #[serde(default)]
+    pub test_recipients: Vec<String>,
+    /// How often the scheduler looks for due issues: they go out at most this late.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub scheduler_interval_seconds: u64,
+}
+
+impl Default for NewsletterIssueSettings {
+    fn default() -> Self {
+        Self {
+            test_recipients: Vec::new(),
+            scheduler_interval_seconds: 30,
+        }
+    }
+}
+
+impl NewsletterIssueSettings {
+    pub fn scheduler_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.scheduler_interval_seconds)
+    }
+
+    pub fn test_recipients(&self) -> Result<Vec<SubscriberEmail>, ValidationErrors> {
+        self.test_recipients
+            .iter()
+            .map(|email| SubscriberEmail::parse(email.clone()))
+            .collect()
+    }
 }
 
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
@@ -1,7 +1,11 @@
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_token;
+mod unsubscribe_token;
 
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use subscriber_name::{SubscriberName, SubscriberNameError};
+pub use subscription_token::SubscriptionToken;
+pub use unsubscribe_token::UnsubscribeToken;
@@ -3,8 +3,19 @@ use unicode_segmentation::UnicodeSegmentation;
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
+/// Why a subscriber name was rejected.
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum SubscriberNameError {
+    #[error("The name is empty.")]
+    Empty,
+    #[error("The name is longer than 256 characters.")]
+    TooLong,
+    #[error("The name contains a forbidden character.")]
+    ForbiddenCharacter,
+}
+
 impl SubscriberName {
-    pub fn parse(s: String) -> Result<Self, String> {
+    pub fn parse(s: String) -> Result<Self, SubscriberNameError> {
         let is_empty_or_whitespace = s.trim().is_empty();
 
         let is_too_long = s.graphemes(true).count() > 256;
@@ -12,8 +23,12 @@ impl SubscriberName {
         let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
         let contains_forbidden_characters = s.chars().any(|g| forbidden_characters.contains(&g));
 
-        if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
-            Err(format!("{} is not a valid subscriber name.", s))
+        if is_empty_or_whitespace {
+            Err(SubscriberNameError::Empty)
+        } else if is_too_long {
+            Err(SubscriberNameError::TooLong)
+        } else if contains_forbidden_characters {
+            Err(SubscriberNameError::ForbiddenCharacter)
         } else {
             Ok(Self(s))
         }
@@ -29,8 +44,8 @@ impl AsRef<str> for SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberName;
-    use claims::{assert_err, assert_ok};
+    use super::{SubscriberName, SubscriberNameError};
+    use claims::{assert_err_eq, assert_ok};
 
     #[test]
     fn a_256_grapheme_long_name_is_valid() {
@@ -41,14 +56,27 @@ mod tests {
     #[test]
     fn a_name_longer_than_256_graphemes_is_rejected() {
         let name = "a".repeat(257);
-        assert_err!(SubscriberName::parse(name));
+        assert_err_eq!(SubscriberName::parse(name), SubscriberNameError::TooLong);
     }
 
     #[test]
     fn names_containing_invalid_character_are_rejected() {
         for name in &['/', '(', ')', '"', '<', '>', '\\', '{', '}'] {
             let name = name.to_string();
-            assert_err!(SubscriberName::parse(name));
+            assert_err_eq!(
+                SubscriberName::parse(name),
+                SubscriberNameError::ForbiddenCharacter
+            );
+        }
+    }
+
+    #[test]
+    fn empty_or_whitespace_only_names_are_rejected() {
+        for name in ["", " ", "\t\n"] {
+            assert_err_eq!(
+                SubscriberName::parse(name.to_string()),
+                SubscriberNameError::Empty
+            );
         }
     }
 
@@ -0,0 +1,93 @@
+use hmac::{Hmac, Mac};
+use rand::distr::Alphanumeric;
+use rand::{Rng, rng};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use subtle::ConstantTimeEq;
+
+/// The token embedded in confirmation links.
+///
+/// Only its keyed hash is ever stored: someone who can read the database
+/// still cannot produce a working confirmation link.
+#[derive(Debug)]
+pub struct SubscriptionToken(String);
+
+impl SubscriptionToken {
+    /// Generate a random 25-char-long case-sensitive subscription token
+    pub fn generate() -> Self {
+        let mut rng = rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(25)
+            .collect();
+        Self(token)
+    }
+
+    /// HMAC-SHA256 of the token, hex-encoded.
+    pub fn hash(&self, hmac_secret: &SecretString) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes()).unwrap();
+        // Domain separation: the same secret signs unsubscribe links
+        mac.update(b"subscription-token:");
+        mac.update(self.0.as_bytes());
+        format!("{:x}", mac.finalize().into_bytes())
+    }
+
+    /// Check the token against a stored hash, in constant time.
+    pub fn matches(&self, stored_hash: &str, hmac_secret: &SecretString) -> bool {
+        self.hash(hmac_secret)
+            .as_bytes()
+            .ct_eq(stored_hash.as_bytes())
+            .into()
+    }
+}
+
+impl From<String> for SubscriptionToken {
+    fn from(token: String) -> Self {
+        Self(token)
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriptionToken;
+    use secrecy::SecretString;
+
+    fn secret() -> SecretString {
+        SecretString::from("super-secret-key")
+    }
+
+    #[test]
+    fn generated_tokens_are_25_alphanumeric_chars() {
+        let token = SubscriptionToken::generate();
+        assert_eq!(token.as_ref().len(), 25);
+        assert!(token.as_ref().chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn a_token_matches_its_own_hash() {
+        let token = SubscriptionToken::generate();
+        let hash = token.hash(&secret());
+        assert!(token.matches(&hash, &secret()));
+        assert!(!hash.contains(token.as_ref()));
+    }
+
+    #[test]
+    fn a_token_does_not_match_a_hash_computed_with_another_secret() {
+        let token = SubscriptionToken::generate();
+        let hash = token.hash(&SecretString::from("another-key"));
+        assert!(!token.matches(&hash, &secret()));
+    }
+
+    #[test]
+    fn a_token_does_not_match_another_token_hash() {
+        let hash = SubscriptionToken::generate().hash(&secret());
+        assert!(!SubscriptionToken::generate().matches(&hash, &secret()));
+    }
+}
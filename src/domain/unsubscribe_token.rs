@@ -0,0 +1,92 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// Opaque token identifying a subscriber in unsubscribe links.
+///
+/// It is the subscriber id followed by an HMAC-SHA256 tag of that id, both base64url-encoded:
+/// nothing needs to be stored, and it cannot be forged without the application's secret.
+#[derive(Debug)]
+pub struct UnsubscribeToken(String);
+
+impl UnsubscribeToken {
+    pub fn generate(subscriber_id: Uuid, hmac_secret: &SecretString) -> Self {
+        let tag = mac(subscriber_id, hmac_secret).finalize().into_bytes();
+        let mut payload = subscriber_id.as_bytes().to_vec();
+        payload.extend_from_slice(&tag);
+        Self(URL_SAFE_NO_PAD.encode(payload))
+    }
+
+    /// Return the id of the subscriber the token was issued for, if the token is genuine.
+    pub fn verify(token: &str, hmac_secret: &SecretString) -> Option<Uuid> {
+        let payload = URL_SAFE_NO_PAD.decode(token).ok()?;
+        if payload.len() <= 16 {
+            return None;
+        }
+        let (id, tag) = payload.split_at(16);
+        let subscriber_id = Uuid::from_slice(id).ok()?;
+        // `verify_slice` compares in constant time
+        mac(subscriber_id, hmac_secret).verify_slice(tag).ok()?;
+        Some(subscriber_id)
+    }
+}
+
+impl AsRef<str> for UnsubscribeToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+fn mac(subscriber_id: Uuid, hmac_secret: &SecretString) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(hmac_secret.expose_secret().as_bytes()).unwrap();
+    // Domain separation: never accept a tag computed for another purpose
+    mac.update(b"unsubscribe:");
+    mac.update(subscriber_id.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claims::{assert_none, assert_some_eq};
+    use secrecy::SecretString;
+    use uuid::Uuid;
+
+    fn secret() -> SecretString {
+        SecretString::from("super-secret-key")
+    }
+
+    #[test]
+    fn a_generated_token_verifies_to_its_subscriber() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::generate(subscriber_id, &secret());
+        assert_some_eq!(
+            UnsubscribeToken::verify(token.as_ref(), &secret()),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn a_token_signed_with_another_secret_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &SecretString::from("another-key"));
+        assert_none!(UnsubscribeToken::verify(token.as_ref(), &secret()));
+    }
+
+    #[test]
+    fn a_tampered_token_is_rejected() {
+        let token = UnsubscribeToken::generate(Uuid::new_v4(), &secret());
+        let mut tampered = token.as_ref().to_owned();
+        let last = if tampered.ends_with('A') { "B" } else { "A" };
+        tampered.replace_range(tampered.len() - 1.., last);
+        assert_none!(UnsubscribeToken::verify(&tampered, &secret()));
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        assert_none!(UnsubscribeToken::verify("", &secret()));
+        assert_none!(UnsubscribeToken::verify("not-a-token!", &secret()));
+    }
+}
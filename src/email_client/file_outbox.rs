@@ -0,0 +1,85 @@
+use super::smtp::build_message;
+use super::{Email, EmailError, EmailSender};
+use anyhow::Context;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Writes every email as an `.eml` file into a local directory instead of sending it.
+///
+/// Meant for local development: open the files with any mail client to check what
+/// would have been sent, without needing a provider account.
+pub struct FileOutboxSender {
+    directory: PathBuf,
+}
+
+impl FileOutboxSender {
+    pub fn new(directory: PathBuf) -> Self {
+        Self { directory }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for FileOutboxSender {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let message = build_message(email).map_err(EmailError::permanent)?;
+        tokio::fs::create_dir_all(&self.directory)
+            .await
+            .context("Failed to create the outbox directory")
+            .map_err(EmailError::permanent)?;
+        // Prefix with a timestamp so that the outbox lists emails in the order they were sent.
+        let file_name = format!(
+            "{}-{}.eml",
+            chrono::Utc::now().format("%Y%m%dT%H%M%S%.6f"),
+            Uuid::new_v4()
+        );
+        let path = self.directory.join(file_name);
+        tokio::fs::write(&path, message.formatted())
+            .await
+            .with_context(|| format!("Failed to write the email to {}", path.display()))
+            .map_err(EmailError::permanent)?;
+        tracing::info!(path = %path.display(), "Email written to the outbox");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileOutboxSender;
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailSender};
+    use claims::assert_ok;
+    use uuid::Uuid;
+
+    #[tokio::test]
+    async fn send_writes_an_eml_file_into_the_outbox() {
+        let directory = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let outbox = FileOutboxSender::new(directory.clone());
+        let from = SubscriberEmail::parse("newsletter@example.com".into()).unwrap();
+        let to = SubscriberEmail::parse("ursula_le_guin@example.com".into()).unwrap();
+        let email = Email {
+            from: &from,
+            to: &to,
+            subject: "Issue #1",
+            html_body: "<p>Hello from HTML</p>",
+            text_body: "Hello from plain text",
+            headers: &[(
+                "List-Unsubscribe".to_string(),
+                "<https://example.com/unsubscribe>".to_string(),
+            )],
+        };
+
+        assert_ok!(outbox.send(&email).await);
+
+        let files: Vec<_> = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let content = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(content.contains("Subject: Issue #1"));
+        assert!(content.contains("To: ursula_le_guin@example.com"));
+        assert!(content.contains("List-Unsubscribe: <https://example.com/unsubscribe>"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,551 @@
+//! src/email_client/mod.rs
+
+mod file_outbox;
+mod postmark;
+mod smtp;
+
+pub use file_outbox::FileOutboxSender;
+pub use postmark::PostmarkSender;
+pub use smtp::{SmtpSender, SmtpTls};
+
+use crate::domain::SubscriberEmail;
+use crate::utils::error_chain_fmt;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A fully-addressed email, ready to be handed over to an [`EmailSender`].
+pub struct Email<'a> {
+    pub from: &'a SubscriberEmail,
+    pub to: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_body: &'a str,
+    pub text_body: &'a str,
+    /// Extra headers, e.g. `List-Unsubscribe`, as `(name, value)` pairs.
+    pub headers: &'a [(String, String)],
+}
+
+/// A way of getting an email out of the door: an HTTP API, an SMTP relay, a local folder...
+#[async_trait::async_trait]
+pub trait EmailSender: Send + Sync {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError>;
+}
+
+/// Addresses that must never be mailed again, whatever the reason they are being sent to.
+#[async_trait::async_trait]
+pub trait SuppressionList: Send + Sync {
+    async fn is_suppressed(&self, email: &SubscriberEmail) -> Result<bool, anyhow::Error>;
+}
+
+#[derive(thiserror::Error)]
+pub enum EmailError {
+    /// The provider could not take the email right now, but might later:
+    /// timeouts, connection failures, rate limiting, server-side errors.
+    #[error("Failed to deliver the email, the error might be transient.")]
+    Transient {
+        #[source]
+        source: anyhow::Error,
+        /// How long the provider asked us to wait before trying again, if it did.
+        retry_after: Option<Duration>,
+    },
+    /// Trying again is not going to help, e.g. the provider rejected the request as invalid.
+    #[error("Failed to deliver the email.")]
+    Permanent(#[source] anyhow::Error),
+    /// The recipient is on the suppression list: nothing was sent, and nothing ever will be.
+    #[error("The recipient is on the suppression list.")]
+    Suppressed,
+}
+
+impl EmailError {
+    fn transient(source: impl Into<anyhow::Error>) -> Self {
+        Self::Transient {
+            source: source.into(),
+            retry_after: None,
+        }
+    }
+
+    fn permanent(source: impl Into<anyhow::Error>) -> Self {
+        Self::Permanent(source.into())
+    }
+}
+
+impl std::fmt::Debug for EmailError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Sends emails on behalf of the application through the configured [`EmailSender`],
+/// retrying transient failures according to its [`RetryPolicy`].
+///
+/// Recipients on the [`SuppressionList`] are checked before every email and never sent to.
+#[derive(Clone)]
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    provider: Arc<dyn EmailSender>,
+    retry_policy: RetryPolicy,
+    suppressions: Arc<dyn SuppressionList>,
+}
+
+impl EmailClient {
+    pub fn new(
+        sender: SubscriberEmail,
+        provider: Arc<dyn EmailSender>,
+        retry_policy: RetryPolicy,
+        suppressions: Arc<dyn SuppressionList>,
+    ) -> Self {
+        Self {
+            sender,
+            provider,
+            retry_policy,
+            suppressions,
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailError> {
+        self.send_email_with_headers(recipient, subject, html_content, text_content, &[])
+            .await
+    }
+
+    pub async fn send_email_with_headers(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[(String, String)],
+    ) -> Result<(), EmailError> {
+        // If we cannot tell, we do not send: the caller can try again later.
+        if self
+            .suppressions
+            .is_suppressed(&recipient)
+            .await
+            .map_err(EmailError::transient)?
+        {
+            return Err(EmailError::Suppressed);
+        }
+        let email = Email {
+            from: &self.sender,
+            to: &recipient,
+            subject,
+            html_body: html_content,
+            text_body: text_content,
+            headers,
+        };
+        let mut attempt = 1;
+        loop {
+            let error = match self.provider.send(&email).await {
+                Ok(()) => return Ok(()),
+                Err(e) => e,
+            };
+            let retry_after = match &error {
+                EmailError::Transient { retry_after, .. } => *retry_after,
+                EmailError::Permanent(_) | EmailError::Suppressed => return Err(error),
+            };
+            if attempt >= self.retry_policy.max_attempts {
+                return Err(error);
+            }
+            let delay = match retry_after {
+                // Do not retry earlier than the provider asked us to, but do not wait forever either.
+                Some(delay) if delay > self.retry_policy.max_delay => return Err(error),
+                Some(delay) => delay,
+                None => self.retry_policy.backoff(attempt),
+            };
+            tracing::warn!(
+                error.cause_chain = ?error,
+                attempt,
+                retry_in_milliseconds = delay.as_millis() as u64,
+                "Failed to send an email, retrying",
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Exponential backoff settings for transient delivery failures.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, base_delay: Duration, max_delay: Duration, jitter: bool) -> Self {
+        Self {
+            // Whatever the configuration says, we always make at least one attempt.
+            max_attempts: max_attempts.max(1),
+            base_delay,
+            max_delay,
+            jitter,
+        }
+    }
+
+    /// Delay to wait after the given (1-based) failed attempt: `base_delay * 2^(attempt - 1)`,
+    /// capped at `max_delay`. With jitter enabled, a random delay between zero and that value
+    /// is picked instead, so that concurrent senders do not retry in lockstep.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(31);
+        let delay = self
+            .base_delay
+            .saturating_mul(2u32.pow(exponent))
+            .min(self.max_delay);
+        if self.jitter {
+            let millis = delay.as_millis() as u64;
+            Duration::from_millis(rand::rng().random_range(0..=millis))
+        } else {
+            delay
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_matches, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::SecretString;
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    use crate::{
+        domain::SubscriberEmail,
+        email_client::{EmailClient, EmailError, PostmarkSender, RetryPolicy, SuppressionList},
+    };
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse request body as JSON
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // Check that all mandatory fields are populated
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                // If parsing failed, do not match request
+                false
+            }
+        }
+    }
+
+    /// Suppresses exactly the given addresses.
+    struct Suppressions(Vec<SubscriberEmail>);
+
+    #[async_trait::async_trait]
+    impl SuppressionList for Suppressions {
+        async fn is_suppressed(&self, email: &SubscriberEmail) -> Result<bool, anyhow::Error> {
+            Ok(self.0.iter().any(|e| e.as_ref() == email.as_ref()))
+        }
+    }
+
+    /// Generate a random email subject
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+    /// Generate a random email content
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+    /// Generate a random subscriber email
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+    /// Get a test instance of `EmailClient`.
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            email(),
+            Arc::new(postmark(base_url)),
+            RetryPolicy::new(
+                3,
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+                false,
+            ),
+            Arc::new(Suppressions(vec![])),
+        )
+    }
+    /// Get a test instance of `PostmarkSender`.
+    fn postmark(base_url: String) -> PostmarkSender {
+        PostmarkSender::new(
+            base_url,
+            SecretString::new(Faker.fake::<String>().into_boxed_str()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn send_email_fires_request_to_base_url() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json")) // Extend the request matcher
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher) // Use custom defined matcher
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_failes_if_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(3) // One attempt, then two retries
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_server_takes_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_a_retry_succeeds() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        // Mocks are matched in mounting order: the first one is used up after one call
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_does_not_retry_client_errors() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_retries_dropped_connections() {
+        // A server which hangs up on every request, like a proxy resetting connections
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let n_connections = Arc::new(AtomicUsize::new(0));
+        let counter = n_connections.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                drop(stream);
+            }
+        });
+        let email_client = email_client(format!("http://{}", address));
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_matches!(outcome, Err(EmailError::Transient { .. }));
+        assert_eq!(n_connections.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn send_email_honors_retry_after() {
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            email(),
+            Arc::new(postmark(mock_server.uri())),
+            RetryPolicy::new(2, Duration::from_millis(10), Duration::from_secs(5), false),
+            Arc::new(Suppressions(vec![])),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let start = std::time::Instant::now();
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_if_retry_after_exceeds_max_delay() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        assert_err!(outcome);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_max_delay() {
+        let policy = RetryPolicy::new(
+            10,
+            Duration::from_millis(100),
+            Duration::from_millis(1000),
+            false,
+        );
+
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(2), Duration::from_millis(200));
+        assert_eq!(policy.backoff(4), Duration::from_millis(800));
+        assert_eq!(policy.backoff(5), Duration::from_millis(1000));
+        assert_eq!(policy.backoff(64), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jittered_backoff_never_exceeds_the_exponential_delay() {
+        let policy = RetryPolicy::new(
+            10,
+            Duration::from_millis(100),
+            Duration::from_millis(1000),
+            true,
+        );
+
+        for _ in 0..100 {
+            assert!(policy.backoff(3) <= Duration::from_millis(400));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_with_headers_forwards_custom_headers() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!({
+                "Headers": [{"Name": "List-Unsubscribe", "Value": "<https://example.com/u>"}]
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let headers = [(
+            "List-Unsubscribe".to_string(),
+            "<https://example.com/u>".to_string(),
+        )];
+        let outcome = email_client
+            .send_email_with_headers(email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_skips_suppressed_recipients() {
+        let mock_server = MockServer::start().await;
+        let recipient = email();
+        let email_client = EmailClient::new(
+            email(),
+            Arc::new(postmark(mock_server.uri())),
+            RetryPolicy::new(
+                3,
+                Duration::from_millis(10),
+                Duration::from_millis(100),
+                false,
+            ),
+            Arc::new(Suppressions(vec![recipient.clone()])),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(recipient, &subject(), &content(), &content())
+            .await;
+
+        assert_matches!(outcome, Err(EmailError::Suppressed));
+    }
+}
@@ -0,0 +1,110 @@
+use super::{Email, EmailError, EmailSender};
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Client, StatusCode};
+use secrecy::{ExposeSecret, SecretString};
+use std::time::{Duration, SystemTime};
+
+/// Delivers emails through Postmark's HTTP API.
+pub struct PostmarkSender {
+    http_client: Client,
+    base_url: String,
+    auth_token: SecretString,
+}
+
+impl PostmarkSender {
+    pub fn new(base_url: String, auth_token: SecretString, timeout: Duration) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            auth_token,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for PostmarkSender {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let url = format!("{}/email", self.base_url);
+        let request_body = SendEmailRequest {
+            from: email.from.as_ref(),
+            to: email.to.as_ref(),
+            subject: email.subject,
+            html_body: email.html_body,
+            text_body: email.text_body,
+            headers: email
+                .headers
+                .iter()
+                .map(|(name, value)| Header { name, value })
+                .collect(),
+        };
+        let response = self
+            .http_client
+            .post(&url)
+            .header("X-Postmark-Server-Token", self.auth_token.expose_secret())
+            .json(&request_body) // Sets `Content-Type` to `application/json` by default
+            .send()
+            .await
+            .map_err(|e| {
+                if is_retryable(&e) {
+                    EmailError::transient(e)
+                } else {
+                    EmailError::permanent(e)
+                }
+            })?;
+        let retry_after = retry_after(response.headers());
+        match response.error_for_status() {
+            Ok(_) => Ok(()),
+            Err(e) if is_retryable(&e) => Err(EmailError::Transient {
+                source: e.into(),
+                retry_after,
+            }),
+            Err(e) => Err(EmailError::permanent(e)),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+/// Timeouts, connection failures, rate limiting and server-side errors are worth another try,
+/// and so are connections dropped while the request or the response were in flight.
+/// Anything else (e.g. a 4xx for a malformed request) is going to fail again.
+fn is_retryable(error: &reqwest::Error) -> bool {
+    if error.is_timeout() || error.is_connect() || error.is_request() || error.is_body() {
+        return true;
+    }
+    match error.status() {
+        Some(status) => status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+        None => false,
+    }
+}
+
+/// Parse a `Retry-After` header, given either as a number of seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
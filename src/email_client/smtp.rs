@@ -0,0 +1,219 @@
+use super::{Email, EmailError, EmailSender};
+use anyhow::Context;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use secrecy::{ExposeSecret, SecretString};
+use std::time::Duration;
+
+/// How the connection to the SMTP relay is secured.
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpTls {
+    /// Plain-text connection. Only meant for relays on localhost or in tests.
+    None,
+    /// Upgrade a plain-text connection with `STARTTLS` (usually port 587).
+    Starttls,
+    /// TLS from the first byte (usually port 465).
+    Wrapper,
+}
+
+/// Delivers emails through a generic SMTP relay.
+pub struct SmtpSender {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpSender {
+    pub fn new(
+        host: &str,
+        port: u16,
+        tls: SmtpTls,
+        credentials: Option<(String, SecretString)>,
+        timeout: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let builder = match tls {
+            SmtpTls::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+            SmtpTls::Starttls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)
+                .context("Failed to configure a STARTTLS connection to the SMTP relay")?,
+            SmtpTls::Wrapper => AsyncSmtpTransport::<Tokio1Executor>::relay(host)
+                .context("Failed to configure a TLS connection to the SMTP relay")?,
+        };
+        let mut builder = builder.port(port).timeout(Some(timeout));
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        Ok(Self {
+            transport: builder.build(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailSender for SmtpSender {
+    async fn send(&self, email: &Email<'_>) -> Result<(), EmailError> {
+        let message = build_message(email).map_err(EmailError::permanent)?;
+        self.transport.send(message).await.map_err(|e| {
+            // 5xx replies and malformed requests will fail again, anything else
+            // (4xx replies, timeouts, dropped connections, ...) might not.
+            if e.is_permanent() || e.is_client() {
+                EmailError::permanent(e)
+            } else {
+                EmailError::transient(e)
+            }
+        })?;
+        Ok(())
+    }
+}
+
+/// Build a MIME message with both the HTML and the plain-text bodies as alternatives.
+pub(super) fn build_message(email: &Email<'_>) -> Result<Message, anyhow::Error> {
+    let from: Mailbox = email
+        .from
+        .as_ref()
+        .parse()
+        .context("Invalid sender address")?;
+    let to: Mailbox = email
+        .to
+        .as_ref()
+        .parse()
+        .context("Invalid recipient address")?;
+    let mut builder = Message::builder().from(from).to(to).subject(email.subject);
+    for (name, value) in email.headers {
+        let name = HeaderName::new_from_ascii(name.clone())
+            .with_context(|| format!("Invalid header name: {}", name))?;
+        builder = builder.raw_header(HeaderValue::new(name, value.clone()));
+    }
+    let message = builder
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_body.to_owned(),
+            email.html_body.to_owned(),
+        ))
+        .context("Failed to build the email message")?;
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SmtpSender, SmtpTls};
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailError, EmailSender};
+    use claims::{assert_matches, assert_ok};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    /// A minimal SMTP server accepting a single connection, standing in for a real relay.
+    /// It replies to `RCPT TO` with `rcpt_reply` and hands back the `DATA` payload it received.
+    async fn spawn_smtp_stand_in(rcpt_reply: &'static str) -> (u16, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (data_tx, data_rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Ok(Some(line)) = lines.next_line().await {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        writer.write_all(b"250 2.0.0 Queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                        data.push('\n');
+                    }
+                    continue;
+                }
+                let command = line.to_ascii_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250 localhost\r\n"
+                } else if command.starts_with("RCPT TO") {
+                    rcpt_reply.as_bytes()
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 2.0.0 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 2.0.0 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+            let _ = data_tx.send(data);
+        });
+        (port, data_rx)
+    }
+
+    fn sender(port: u16) -> SmtpSender {
+        SmtpSender::new(
+            "127.0.0.1",
+            port,
+            SmtpTls::None,
+            None,
+            Duration::from_secs(1),
+        )
+        .unwrap()
+    }
+
+    fn email_parts() -> (SubscriberEmail, SubscriberEmail) {
+        (
+            SubscriberEmail::parse("newsletter@example.com".into()).unwrap(),
+            SubscriberEmail::parse("ursula_le_guin@example.com".into()).unwrap(),
+        )
+    }
+
+    /// The same email every time: the tests only differ in how the relay replies.
+    fn email<'a>(from: &'a SubscriberEmail, to: &'a SubscriberEmail) -> Email<'a> {
+        Email {
+            from,
+            to,
+            subject: "Issue #1",
+            html_body: "<p>Hello from HTML</p>",
+            text_body: "Hello from plain text",
+            headers: &[],
+        }
+    }
+
+    #[tokio::test]
+    async fn send_delivers_both_bodies_to_the_relay() {
+        let (port, data) = spawn_smtp_stand_in("250 2.1.5 OK\r\n").await;
+        let (from, to) = email_parts();
+
+        let outcome = sender(port).send(&email(&from, &to)).await;
+
+        assert_ok!(outcome);
+        let data = data.await.unwrap();
+        assert!(data.contains("Subject: Issue #1"));
+        assert!(data.contains("To: ursula_le_guin@example.com"));
+        assert!(data.contains("Content-Type: text/plain"));
+        assert!(data.contains("Content-Type: text/html"));
+    }
+
+    #[tokio::test]
+    async fn a_4xx_reply_is_a_transient_failure() {
+        let (port, _) = spawn_smtp_stand_in("451 4.3.0 Try again later\r\n").await;
+        let (from, to) = email_parts();
+
+        let outcome = sender(port).send(&email(&from, &to)).await;
+
+        assert_matches!(outcome, Err(EmailError::Transient { .. }));
+    }
+
+    #[tokio::test]
+    async fn a_5xx_reply_is_a_permanent_failure() {
+        let (port, _) = spawn_smtp_stand_in("550 5.1.1 No such user\r\n").await;
+        let (from, to) = email_parts();
+
+        let outcome = sender(port).send(&email(&from, &to)).await;
+
+        assert_matches!(outcome, Err(EmailError::Permanent(_)));
+    }
+}
@@ -0,0 +1,142 @@
+use axum::Json;
+use axum::extract::{Form, FromRequest, Request};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::de::DeserializeOwned;
+
+/// Deserialize the request body either as an urlencoded form or as JSON,
+/// depending on its `Content-Type`. Anything else is rejected with `415 Unsupported Media Type`.
+pub struct FormOrJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for FormOrJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        match content_type(req.headers()).as_deref() {
+            Some("application/x-www-form-urlencoded") => {
+                let Form(value) = Form::<T>::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                Ok(Self(value))
+            }
+            Some("application/json") => {
+                let Json(value) = Json::<T>::from_request(req, state)
+                    .await
+                    .map_err(IntoResponse::into_response)?;
+                Ok(Self(value))
+            }
+            _ => Err(StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response()),
+        }
+    }
+}
+
+/// Whether the client listed `application/json` among the media types it accepts.
+pub fn accepts_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|media_range| {
+            let mut parts = media_range.split(';').map(str::trim);
+            let is_json = parts
+                .next()
+                .is_some_and(|m| m.eq_ignore_ascii_case("application/json"));
+            is_json && quality(parts).is_some_and(|q| q > 0.0)
+        })
+}
+
+/// The language tags of `Accept-Language`, most preferred first. `*` and `q=0` are left out.
+pub fn accepted_languages(headers: &HeaderMap) -> Vec<String> {
+    let mut languages: Vec<(String, f32)> = headers
+        .get_all(header::ACCEPT_LANGUAGE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|language_range| {
+            let mut parts = language_range.split(';').map(str::trim);
+            let tag = parts.next().filter(|tag| !tag.is_empty() && *tag != "*")?;
+            // Refused languages are dropped before ranking, rather than ranked last
+            let quality = quality(parts).filter(|q| *q > 0.0)?;
+            Some((tag.to_string(), quality))
+        })
+        .collect();
+    // Stable: ties keep the order the client listed them in
+    languages.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    languages.into_iter().map(|(tag, _)| tag).collect()
+}
+
+/// The weight of an element of `Accept` or `Accept-Language`, from its parameters: `1` if it
+/// has none, `None` if it is malformed. `0` means "not acceptable", in any spelling (`Q=0.000`).
+fn quality<'a>(mut parameters: impl Iterator<Item = &'a str>) -> Option<f32> {
+    parameters
+        .find_map(|p| {
+            let (name, value) = p.split_once('=')?;
+            name.trim().eq_ignore_ascii_case("q").then(|| value.trim())
+        })
+        .map_or(Some(1.0), |q| {
+            q.parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))
+        })
+}
+
+/// The lowercased `type/subtype` of the body, without its parameters (e.g. `charset`).
+pub(crate) fn content_type(headers: &HeaderMap) -> Option<String> {
+    let value = headers.get(header::CONTENT_TYPE)?.to_str().ok()?;
+    let essence = value.split(';').next()?.trim();
+    Some(essence.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accepted_languages, accepts_json};
+    use axum::http::{HeaderMap, HeaderValue, header};
+
+    fn accept(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, HeaderValue::from_static(value));
+        headers
+    }
+
+    #[test]
+    fn json_is_accepted_when_listed() {
+        assert!(accepts_json(&accept("application/json")));
+        assert!(accepts_json(&accept("text/html, application/json;q=0.9")));
+        assert!(accepts_json(&accept("Application/JSON")));
+    }
+
+    #[test]
+    fn json_is_not_accepted_otherwise() {
+        assert!(!accepts_json(&HeaderMap::new()));
+        assert!(!accepts_json(&accept("text/html")));
+        assert!(!accepts_json(&accept("*/*")));
+        assert!(!accepts_json(&accept("application/json;q=0")));
+        assert!(!accepts_json(&accept("application/json; Q=0.000")));
+    }
+
+    #[test]
+    fn languages_are_sorted_by_preference() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_LANGUAGE,
+            HeaderValue::from_static("de;q=0.5, fr-CH, *;q=0.1, en;q=0.9, it;q=0, es;q=0.5"),
+        );
+
+        assert_eq!(accepted_languages(&headers), ["fr-CH", "en", "de", "es"]);
+        assert!(accepted_languages(&HeaderMap::new()).is_empty());
+    }
+
+    #[test]
+    fn refused_languages_are_left_out() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::ACCEPT_LANGUAGE,
+            HeaderValue::from_static("fr;q=0.000, it; Q=0, de;q=0.0, en;q=0.1, es;q=oops"),
+        );
+
+        assert_eq!(accepted_languages(&headers), ["en"]);
+    }
+}
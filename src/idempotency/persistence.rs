@@ -0,0 +1,129 @@
+use super::IdempotencyKey;
+use anyhow::Context;
+use axum::body::Body;
+use axum::http::StatusCode;
+use axum::response::Response;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(Debug, sqlx::Type)]
+#[sqlx(type_name = "header_pair")]
+struct HeaderPairRecord {
+    name: String,
+    value: Vec<u8>,
+}
+
+pub enum NextAction {
+    /// First time we see this key: process the request, then hand the transaction to `save_response`.
+    StartProcessing(Transaction<'static, Postgres>),
+    /// The request was already processed: replay the response we stored back then.
+    ReturnSavedResponse(Response),
+}
+
+/// Claim the idempotency key for this request or fetch the response it produced last time.
+///
+/// The claim is an `INSERT` inside a transaction: a concurrent request with the same key
+/// blocks on the primary key until the first one commits, and then replays its response.
+pub async fn try_processing(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<NextAction, anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    let query = sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, $2, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    );
+    let n_inserted_rows = transaction.execute(query).await?.rows_affected();
+    if n_inserted_rows > 0 {
+        Ok(NextAction::StartProcessing(transaction))
+    } else {
+        let saved_response = get_saved_response(db_pool, idempotency_key, user_id)
+            .await?
+            .ok_or_else(|| anyhow::anyhow!("We expected a saved response, we didn't find it"))?;
+        Ok(NextAction::ReturnSavedResponse(saved_response))
+    }
+}
+
+async fn get_saved_response(
+    db_pool: &PgPool,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+) -> Result<Option<Response>, anyhow::Error> {
+    let saved_response = sqlx::query!(
+        r#"
+        SELECT
+            response_status_code as "response_status_code!",
+            response_headers as "response_headers!: Vec<HeaderPairRecord>",
+            response_body as "response_body!"
+        FROM idempotency
+        WHERE
+          user_id = $1 AND
+          idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref()
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    if let Some(r) = saved_response {
+        let status_code = StatusCode::from_u16(r.response_status_code.try_into()?)?;
+        let mut response = Response::builder().status(status_code);
+        for HeaderPairRecord { name, value } in r.response_headers {
+            response = response.header(name, value);
+        }
+        Ok(Some(response.body(Body::from(r.response_body))?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Persist the response for future retries and release the idempotency key.
+pub async fn save_response(
+    mut transaction: Transaction<'static, Postgres>,
+    idempotency_key: &IdempotencyKey,
+    user_id: Uuid,
+    http_response: Response,
+) -> Result<Response, anyhow::Error> {
+    let (response_head, body) = http_response.into_parts();
+    // Buffer the whole body: we need the bytes both in the database and in the response.
+    let body = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .context("Failed to read the response body")?;
+    let status_code = response_head.status.as_u16() as i16;
+    let headers = {
+        let mut h = Vec::with_capacity(response_head.headers.len());
+        for (name, value) in response_head.headers.iter() {
+            let name = name.as_str().to_owned();
+            let value = value.as_bytes().to_owned();
+            h.push(HeaderPairRecord { name, value });
+        }
+        h
+    };
+    let query = sqlx::query_unchecked!(
+        r#"
+        UPDATE idempotency
+        SET
+            response_status_code = $3,
+            response_headers = $4,
+            response_body = $5
+        WHERE
+            user_id = $1 AND
+            idempotency_key = $2
+        "#,
+        user_id,
+        idempotency_key.as_ref(),
+        status_code,
+        headers,
+        body.as_ref()
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+
+    Ok(Response::from_parts(response_head, Body::from(body)))
+}
@@ -0,0 +1,443 @@
+use crate::configuration::Settings;
+use crate::domain::{SubscriberEmail, SubscriptionToken};
+use crate::email_client::{EmailClient, EmailError};
+use crate::routes::{parse_new_subscriber, send_confirmation_email, store_token, unsubscribe_link};
+use crate::startup::get_connection_pool;
+use crate::suppressions::PostgresSuppressionList;
+use crate::templates::EmailTemplates;
+use anyhow::Context;
+use secrecy::SecretString;
+use sqlx::{Executor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{Span, field::display};
+use url::Url;
+use uuid::Uuid;
+
+/// How many times we try to deliver an issue to a subscriber before giving up.
+const MAX_DELIVERY_ATTEMPTS: i16 = 5;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Drain `issue_delivery_queue` and `confirmation_email_queue`, side by side, forever.
+///
+/// Tasks are claimed with `FOR UPDATE SKIP LOCKED`, so any number of workers
+/// (across any number of replicas) can run against the same queue.
+pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration
+        .email_client
+        .client(Arc::new(PostgresSuppressionList::new(db_pool.clone())));
+    let email_templates = configuration
+        .email_templates
+        .templates()
+        .context("Failed to load the email templates")?;
+    let unsubscribe_links = UnsubscribeLinks {
+        base_url: configuration.application.base_url,
+        hmac_secret: configuration.application.hmac_secret,
+    };
+    worker_loop(db_pool, email_client, email_templates, unsubscribe_links).await
+}
+
+/// What the worker needs to sign the links it sends, e.g. the unsubscribe link of every issue.
+pub struct UnsubscribeLinks {
+    pub base_url: Url,
+    pub hmac_secret: SecretString,
+}
+
+async fn worker_loop(
+    db_pool: PgPool,
+    email_client: EmailClient,
+    email_templates: EmailTemplates,
+    unsubscribe_links: UnsubscribeLinks,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_execute_tasks(
+            &db_pool,
+            &email_client,
+            &email_templates,
+            &unsubscribe_links,
+        )
+        .await
+        {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Take one task from each queue, so that a large issue does not hold up confirmations.
+pub async fn try_execute_tasks(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    email_templates: &EmailTemplates,
+    unsubscribe_links: &UnsubscribeLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let issue = try_execute_task(db_pool, email_client, email_templates, unsubscribe_links).await;
+    let confirmation =
+        try_send_confirmation(db_pool, email_client, email_templates, unsubscribe_links).await;
+    match (issue?, confirmation?) {
+        (ExecutionOutcome::EmptyQueue, ExecutionOutcome::EmptyQueue) => {
+            Ok(ExecutionOutcome::EmptyQueue)
+        }
+        _ => Ok(ExecutionOutcome::TaskCompleted),
+    }
+}
+
+#[tracing::instrument(
+    skip_all,
+    fields(
+        newsletter_issue_id=tracing::field::Empty,
+        subscriber_email=tracing::field::Empty
+    ),
+    err
+)]
+pub async fn try_execute_task(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    email_templates: &EmailTemplates,
+    unsubscribe_links: &UnsubscribeLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let task = dequeue_task(db_pool).await?;
+    let Some((transaction, task)) = task else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("newsletter_issue_id", display(task.newsletter_issue_id))
+        .record("subscriber_email", display(&task.subscriber_email));
+
+    // The subscriber may have unsubscribed since the issue was published.
+    let Some(subscriber) = get_confirmed_subscriber(db_pool, &task.subscriber_email).await? else {
+        tracing::info!("Skipping a subscriber who is no longer confirmed.");
+        delete_task(transaction, &task).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+
+    match SubscriberEmail::parse(task.subscriber_email.clone()) {
+        Ok(email) => {
+            let issue = get_issue(db_pool, task.newsletter_issue_id).await?;
+            let link = unsubscribe_link(
+                &unsubscribe_links.base_url,
+                subscriber.id,
+                &unsubscribe_links.hmac_secret,
+            )
+            .context("Failed to build the unsubscribe link")?;
+            // RFC 8058: lets mail clients offer a one-click unsubscribe button
+            let headers = [
+                ("List-Unsubscribe".to_string(), format!("<{}>", link)),
+                (
+                    "List-Unsubscribe-Post".to_string(),
+                    "List-Unsubscribe=One-Click".to_string(),
+                ),
+            ];
+            let body = email_templates.newsletter(
+                subscriber.locale.as_deref(),
+                &issue.title,
+                &issue.html_content,
+                &issue.text_content,
+                &link,
+            );
+            match email_client
+                .send_email_with_headers(email, &body.subject, &body.html, &body.text, &headers)
+                .await
+            {
+                Ok(()) => {}
+                // Retrying would not change a thing
+                Err(EmailError::Suppressed) => {
+                    tracing::info!("Skipping a subscriber on the suppression list.");
+                }
+                Err(e) => {
+                    tracing::error!(
+                        error.cause_chain = ?e,
+                        error.message = %e,
+                        n_retries = task.n_retries,
+                        "Failed to deliver issue to a confirmed subscriber.",
+                    );
+                    return reschedule_or_drop_task(transaction, &task)
+                        .await
+                        .map(|_| ExecutionOutcome::TaskCompleted);
+                }
+            }
+        }
+        Err(e) => {
+            // Addresses were validated on the way in, but the rules may have changed since.
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Skipping a confirmed subscriber. Their stored contact details are invalid",
+            );
+        }
+    }
+    delete_task(transaction, &task).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+}
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(
+    db_pool: &PgPool,
+) -> Result<Option<(PgTransaction, DeliveryTask)>, anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    // The row lock is held until the transaction completes: other workers skip this task meanwhile.
+    let r = sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        SELECT newsletter_issue_id, subscriber_email, n_retries
+        FROM issue_delivery_queue
+        WHERE execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    Ok(r.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Push a failed task back in the queue with an exponential delay, or give up on it.
+#[tracing::instrument(skip_all)]
+async fn reschedule_or_drop_task(
+    mut transaction: PgTransaction,
+    task: &DeliveryTask,
+) -> Result<(), anyhow::Error> {
+    let n_retries = task.n_retries + 1;
+    if n_retries >= MAX_DELIVERY_ATTEMPTS {
+        tracing::error!("Giving up on delivering issue to a confirmed subscriber.");
+        return delete_task(transaction, task).await;
+    }
+    // 1 minute, 2 minutes, 4 minutes, ...
+    let delay_seconds = 60. * 2f64.powi(i32::from(task.n_retries));
+    let query = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = $3,
+            execute_after = now() + make_interval(secs => $4)
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_email,
+        n_retries,
+        delay_seconds
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+struct ConfirmedSubscriber {
+    id: Uuid,
+    locale: Option<String>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_confirmed_subscriber(
+    db_pool: &PgPool,
+    email: &str,
+) -> Result<Option<ConfirmedSubscriber>, anyhow::Error> {
+    let subscriber = sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"SELECT id, locale FROM subscriptions WHERE email = $1 AND status = 'confirmed'"#,
+        email
+    )
+    .fetch_optional(db_pool)
+    .await?;
+    Ok(subscriber)
+}
+
+struct NewsletterIssue {
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue(db_pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(db_pool)
+    .await?;
+    Ok(issue)
+}
+
+/// Send the confirmation email of one subscriber waiting in `confirmation_email_queue`, if any.
+///
+/// Imports queue them instead of sending them while the admin waits: a file can hold thousands.
+#[tracing::instrument(skip_all, fields(subscriber_id=tracing::field::Empty), err)]
+pub async fn try_send_confirmation(
+    db_pool: &PgPool,
+    email_client: &EmailClient,
+    email_templates: &EmailTemplates,
+    links: &UnsubscribeLinks,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    let task = sqlx::query_as!(
+        ConfirmationTask,
+        r#"
+        SELECT q.subscriber_id, q.n_retries, s.email, s.name, s.status, s.locale
+        FROM confirmation_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(task) = task else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current().record("subscriber_id", display(task.subscriber_id));
+
+    // The subscriber may have confirmed, or opted out, since they were queued.
+    if task.status != "pending_confirmation" {
+        tracing::info!("Skipping a subscriber who is no longer pending.");
+        delete_confirmation_task(transaction, task.subscriber_id).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let new_subscriber = match parse_new_subscriber(task.name, task.email) {
+        Ok(new_subscriber) => new_subscriber,
+        Err(errors) => {
+            tracing::warn!(
+                ?errors,
+                "Skipping a pending subscriber. Their stored contact details are invalid",
+            );
+            delete_confirmation_task(transaction, task.subscriber_id).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let subscription_token = SubscriptionToken::generate();
+    if let Err(e) = send_confirmation_email(
+        email_client,
+        email_templates,
+        new_subscriber,
+        task.locale.as_deref(),
+        &links.base_url,
+        subscription_token.as_ref(),
+    )
+    .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            n_retries = task.n_retries,
+            "Failed to send a confirmation email to a pending subscriber.",
+        );
+        return reschedule_or_drop_confirmation_task(
+            transaction,
+            task.subscriber_id,
+            task.n_retries,
+        )
+        .await
+        .map(|_| ExecutionOutcome::TaskCompleted);
+    }
+    // Stored once sent, so that failed attempts leave no token behind.
+    store_token(
+        &mut transaction,
+        task.subscriber_id,
+        &subscription_token,
+        &links.hmac_secret,
+    )
+    .await?;
+    delete_confirmation_task(transaction, task.subscriber_id).await?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct ConfirmationTask {
+    subscriber_id: Uuid,
+    n_retries: i16,
+    email: String,
+    name: String,
+    status: String,
+    locale: Option<String>,
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_confirmation_task(
+    mut transaction: PgTransaction,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    let query = sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscriber_id = $1"#,
+        subscriber_id
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Same backoff as for issues: the subscriber can always ask for a new link if we give up.
+#[tracing::instrument(skip_all)]
+async fn reschedule_or_drop_confirmation_task(
+    mut transaction: PgTransaction,
+    subscriber_id: Uuid,
+    n_retries: i16,
+) -> Result<(), anyhow::Error> {
+    if n_retries + 1 >= MAX_DELIVERY_ATTEMPTS {
+        tracing::error!("Giving up on sending a confirmation email to a pending subscriber.");
+        return delete_confirmation_task(transaction, subscriber_id).await;
+    }
+    let delay_seconds = 60. * 2f64.powi(i32::from(n_retries));
+    let query = sqlx::query!(
+        r#"
+        UPDATE confirmation_email_queue
+        SET
+            n_retries = $2,
+            execute_after = now() + make_interval(secs => $3)
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+        n_retries + 1,
+        delay_seconds
+    );
+    transaction.execute(query).await?;
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use crate::configuration::Settings;
+use crate::newsletter_issues::send_issue;
+use crate::startup::get_connection_pool;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{Span, field::display};
+
+pub enum SchedulingOutcome {
+    IssueSent,
+    NothingDue,
+}
+
+/// Send scheduled issues once they are due, forever.
+///
+/// Every replica runs one: issues are claimed with `FOR UPDATE SKIP LOCKED` and marked as sent
+/// in the transaction which queues them, so each of them is sent by exactly one replica.
+pub async fn run_scheduler_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let db_pool = get_connection_pool(&configuration.database);
+    let interval = configuration.newsletter_issues.scheduler_interval();
+    scheduler_loop(db_pool, interval).await
+}
+
+async fn scheduler_loop(db_pool: PgPool, interval: Duration) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_due_issue(&db_pool).await {
+            Ok(SchedulingOutcome::NothingDue) => {
+                tokio::time::sleep(interval).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(SchedulingOutcome::IssueSent) => {}
+        }
+    }
+}
+
+/// Queue the scheduled issue which has been due the longest, if any, for delivery.
+#[tracing::instrument(skip_all, fields(newsletter_issue_id=tracing::field::Empty), err)]
+pub async fn try_send_due_issue(db_pool: &PgPool) -> Result<SchedulingOutcome, anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    // Another replica sending an issue holds its lock: we skip it rather than wait for it.
+    let due = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_at <= now()
+        ORDER BY scheduled_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(due) = due else {
+        return Ok(SchedulingOutcome::NothingDue);
+    };
+    Span::current().record("newsletter_issue_id", display(due.newsletter_issue_id));
+    send_issue(&mut transaction, due.newsletter_issue_id).await?;
+    transaction.commit().await?;
+    tracing::info!("Sent a scheduled issue");
+    Ok(SchedulingOutcome::IssueSent)
+}
@@ -1,7 +1,21 @@
+pub mod authentication;
 pub mod configuration;
 pub mod domain;
 pub mod email_client;
+pub mod extractors;
+pub mod idempotency;
+pub mod issue_delivery_worker;
+pub mod issue_scheduler;
+pub mod newsletter_issues;
+pub mod problem_details;
+pub mod proof_of_work;
+pub mod rate_limit;
 pub mod routes;
+pub mod session_state;
+pub mod session_store;
 pub mod startup;
 pub mod state;
+pub mod suppressions;
 pub mod telemetry;
+pub mod templates;
+pub mod utils;
@@ -1,9 +1,16 @@
+use std::fmt::{Debug, Display};
+use std::time::Duration;
+use tokio::task::JoinError;
+use tower_sessions::ExpiredDeletion;
 use zero2prod::configuration::get_configuration;
-use zero2prod::startup::Application;
+use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::issue_scheduler::run_scheduler_until_stopped;
+use zero2prod::session_store::PostgresSessionStore;
+use zero2prod::startup::{Application, get_connection_pool};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
-async fn main() -> Result<(), std::io::Error> {
+async fn main() -> anyhow::Result<()> {
     let subscriber = get_subscriber(
         "zero2prod".into(),
         "zero2prod=info,tower_http=info".into(),
@@ -14,7 +21,45 @@ async fn main() -> Result<(), std::io::Error> {
     // Panic if we cannot read configuration
     let config = get_configuration().expect("Failed to read configuration.");
 
-    let app = Application::build(config).await?;
+    let app = Application::build(config.clone()).await?;
+    let app_task = tokio::spawn(app.run());
+    let worker_task = tokio::spawn(run_worker_until_stopped(config.clone()));
+    let session_store = PostgresSessionStore::new(get_connection_pool(&config.database));
+    let scheduler_task = tokio::spawn(run_scheduler_until_stopped(config));
+    let session_cleanup_task =
+        tokio::spawn(session_store.continuously_delete_expired(Duration::from_secs(60 * 60)));
 
-    app.run().await
+    // Whichever task stops first takes the whole process down with it
+    tokio::select! {
+        outcome = app_task => report_exit("API", outcome),
+        outcome = worker_task => report_exit("Background worker", outcome),
+        outcome = scheduler_task => report_exit("Issue scheduler", outcome),
+        outcome = session_cleanup_task => report_exit("Expired session cleanup", outcome),
+    };
+
+    Ok(())
+}
+
+fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
+    match outcome {
+        Ok(Ok(())) => {
+            tracing::info!("{} has exited", task_name)
+        }
+        Ok(Err(e)) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} failed",
+                task_name
+            )
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "{} task failed to complete",
+                task_name
+            )
+        }
+    }
 }
@@ -0,0 +1,181 @@
+use crate::templates::render_markdown;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Where an issue is in its life: written as a draft, then sent to every confirmed subscriber.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    Draft,
+    /// Will be sent later on, without anyone having to be around.
+    Scheduled,
+    /// Handed over to the delivery queue.
+    Sent,
+}
+
+impl IssueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Scheduled => "scheduled",
+            Self::Sent => "sent",
+        }
+    }
+}
+
+/// Either Markdown, which both formats are rendered from, or both formats written by hand.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    Formatted { html: String, text: String },
+}
+
+/// The body of an issue, in every format we keep.
+pub struct IssueContent {
+    pub text: String,
+    pub html: String,
+    /// What `text` and `html` were rendered from, if the issue was written in Markdown.
+    pub markdown: Option<String>,
+}
+
+impl Content {
+    pub fn render(self) -> IssueContent {
+        match self {
+            Self::Markdown { markdown } => {
+                let rendered = render_markdown(&markdown);
+                IssueContent {
+                    text: rendered.text,
+                    html: rendered.html,
+                    markdown: Some(markdown),
+                }
+            }
+            Self::Formatted { html, text } => IssueContent {
+                text,
+                html,
+                markdown: None,
+            },
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct NewsletterIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub markdown_content: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// When the issue was sent, if it was.
+    pub published_at: Option<DateTime<Utc>>,
+    /// When the issue is due, if it is scheduled.
+    pub scheduled_at: Option<DateTime<Utc>>,
+}
+
+/// `published_at` is set if the issue is inserted as `Sent`.
+pub async fn insert_issue(
+    executor: impl PgExecutor<'_>,
+    title: &str,
+    content: &IssueContent,
+    status: IssueStatus,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            markdown_content,
+            status,
+            created_at,
+            updated_at,
+            published_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now(), now(), CASE WHEN $6 = 'sent' THEN now() END)
+        RETURNING newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        "#,
+        Uuid::new_v4(),
+        title,
+        content.text,
+        content.html,
+        content.markdown,
+        status.as_str(),
+    )
+    .fetch_one(executor)
+    .await
+}
+
+pub async fn fetch_issue(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        newsletter_issue_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Mark an issue which has not gone out yet as sent, and queue it for delivery.
+///
+/// `None` if there is no such issue, or if it was sent already. The row stays locked until
+/// `transaction` completes: whoever tries to send the issue concurrently waits for it, then
+/// finds it sent. Issues are delivered once, however many replicas try.
+pub async fn send_issue(
+    transaction: &mut Transaction<'static, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    let issue = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'sent', published_at = now(), updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status <> 'sent'
+        RETURNING newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    if issue.is_some() {
+        enqueue_delivery_tasks(&mut **transaction, newsletter_issue_id).await?;
+    }
+    Ok(issue)
+}
+
+/// Queue the issue for every confirmed subscriber: the delivery worker takes it from there.
+pub async fn enqueue_delivery_tasks(
+    executor: impl PgExecutor<'_>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, email
+        FROM subscriptions
+        WHERE status = 'confirmed'
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(executor)
+    .await?;
+    Ok(())
+}
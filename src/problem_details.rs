@@ -0,0 +1,62 @@
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+
+/// An RFC 7807 `application/problem+json` error body.
+#[derive(serde::Serialize, Debug)]
+pub struct ProblemDetails {
+    #[serde(rename = "type")]
+    pub problem_type: &'static str,
+    pub title: &'static str,
+    pub status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Extension member listing every field that failed validation.
+    #[serde(rename = "invalid-params", skip_serializing_if = "Vec::is_empty")]
+    pub invalid_params: Vec<InvalidParam>,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct InvalidParam {
+    pub name: &'static str,
+    /// Machine-readable, e.g. `empty` or `invalid_email`.
+    pub code: String,
+    /// Human-readable explanation of `code`.
+    pub reason: String,
+}
+
+impl ProblemDetails {
+    /// `400 Bad Request` listing the fields that did not validate.
+    pub fn validation_error(invalid_params: Vec<InvalidParam>) -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: "Your request parameters didn't validate.",
+            status: StatusCode::BAD_REQUEST.as_u16(),
+            detail: None,
+            invalid_params,
+        }
+    }
+
+    /// `429 Too Many Requests`, to go along with a `Retry-After` header.
+    pub fn too_many_requests() -> Self {
+        Self {
+            problem_type: "about:blank",
+            title: "Too Many Requests",
+            status: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            detail: Some("Slow down: try again after the delay in `Retry-After`.".into()),
+            invalid_params: Vec::new(),
+        }
+    }
+}
+
+impl IntoResponse for ProblemDetails {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::BAD_REQUEST);
+        let body = serde_json::to_string(&self).expect("Failed to serialize problem details");
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            body,
+        )
+            .into_response()
+    }
+}
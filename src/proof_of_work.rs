@@ -0,0 +1,260 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, TimeDelta, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, SecretString};
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Self-hosted proof-of-work challenges, to make bulk signups expensive for bots.
+///
+/// A challenge is signed rather than stored: it carries its own id and expiry, and an
+/// HMAC tag proves we issued it. Solving it means finding a `solution` such that
+/// `SHA-256("{challenge}:{solution}")` starts with `difficulty` zero bits.
+/// Only the redemption of a solved challenge needs to be recorded, to stop replays.
+#[derive(Clone)]
+pub struct ProofOfWork {
+    hmac_secret: SecretString,
+    difficulty: u8,
+    ttl: TimeDelta,
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct Challenge {
+    pub challenge: String,
+    pub difficulty: u8,
+    pub algorithm: &'static str,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A challenge that was correctly solved, but not redeemed yet.
+#[derive(Debug)]
+pub struct SolvedChallenge {
+    pub challenge_id: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum ProofOfWorkError {
+    #[error("The proof-of-work challenge or its solution is missing.")]
+    Missing,
+    #[error("The proof-of-work challenge was not issued by us.")]
+    InvalidChallenge,
+    #[error("The proof-of-work challenge has expired.")]
+    Expired,
+    #[error("The proof-of-work solution is wrong.")]
+    WrongSolution,
+    #[error("The proof-of-work challenge has already been used.")]
+    AlreadyRedeemed,
+}
+
+/// Id (16 bytes) + expiry as a unix timestamp (8 bytes)
+const PAYLOAD_LENGTH: usize = 24;
+
+impl ProofOfWork {
+    pub fn new(hmac_secret: SecretString, difficulty: u8, ttl: TimeDelta) -> Self {
+        Self {
+            hmac_secret,
+            difficulty,
+            ttl,
+        }
+    }
+
+    pub fn issue_challenge(&self, now: DateTime<Utc>) -> Challenge {
+        let expires_at = now + self.ttl;
+        let mut payload = Uuid::new_v4().as_bytes().to_vec();
+        payload.extend_from_slice(&expires_at.timestamp().to_be_bytes());
+        let tag = self.mac(&payload).finalize().into_bytes();
+        payload.extend_from_slice(&tag);
+        Challenge {
+            challenge: URL_SAFE_NO_PAD.encode(payload),
+            difficulty: self.difficulty,
+            algorithm: "SHA-256",
+            expires_at,
+        }
+    }
+
+    pub fn verify(
+        &self,
+        challenge: &str,
+        solution: &str,
+        now: DateTime<Utc>,
+    ) -> Result<SolvedChallenge, ProofOfWorkError> {
+        let decoded = URL_SAFE_NO_PAD
+            .decode(challenge)
+            .map_err(|_| ProofOfWorkError::InvalidChallenge)?;
+        if decoded.len() <= PAYLOAD_LENGTH {
+            return Err(ProofOfWorkError::InvalidChallenge);
+        }
+        let (payload, tag) = decoded.split_at(PAYLOAD_LENGTH);
+        // `verify_slice` compares in constant time
+        self.mac(payload)
+            .verify_slice(tag)
+            .map_err(|_| ProofOfWorkError::InvalidChallenge)?;
+
+        let (id, expires_at) = payload.split_at(16);
+        let challenge_id = Uuid::from_slice(id).map_err(|_| ProofOfWorkError::InvalidChallenge)?;
+        let expires_at = i64::from_be_bytes(expires_at.try_into().unwrap());
+        let expires_at =
+            DateTime::from_timestamp(expires_at, 0).ok_or(ProofOfWorkError::InvalidChallenge)?;
+        if expires_at <= now {
+            return Err(ProofOfWorkError::Expired);
+        }
+
+        let digest = Sha256::digest(format!("{}:{}", challenge, solution));
+        if leading_zero_bits(&digest) < u32::from(self.difficulty) {
+            return Err(ProofOfWorkError::WrongSolution);
+        }
+        Ok(SolvedChallenge {
+            challenge_id,
+            expires_at,
+        })
+    }
+
+    fn mac(&self, payload: &[u8]) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes()).unwrap();
+        // Domain separation: the same secret signs other tokens
+        mac.update(b"proof-of-work:");
+        mac.update(payload);
+        mac
+    }
+}
+
+/// Record that a challenge has been used, so that its solution cannot be replayed.
+///
+/// Returns `false` if it had already been redeemed. Expired redemptions are purged
+/// along the way: their challenges are rejected before ever reaching the table.
+#[tracing::instrument(name = "Redeem a proof-of-work challenge", skip(transaction))]
+pub async fn redeem(
+    transaction: &mut Transaction<'static, Postgres>,
+    solved_challenge: &SolvedChallenge,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM proof_of_work_redemptions WHERE expires_at < now()"#)
+        .execute(&mut **transaction)
+        .await?;
+    let redeemed = sqlx::query!(
+        r#"
+        INSERT INTO proof_of_work_redemptions (challenge_id, expires_at)
+        VALUES ($1, $2)
+        ON CONFLICT (challenge_id) DO NOTHING
+        "#,
+        solved_challenge.challenge_id,
+        solved_challenge.expires_at
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(redeemed.rows_affected() == 1)
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in bytes {
+        zeros += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    zeros
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ProofOfWork, ProofOfWorkError, leading_zero_bits};
+    use chrono::{TimeDelta, Utc};
+    use claims::{assert_err_eq, assert_ok};
+    use secrecy::SecretString;
+    use sha2::{Digest, Sha256};
+
+    fn proof_of_work() -> ProofOfWork {
+        ProofOfWork::new(
+            SecretString::from("super-secret-key"),
+            8,
+            TimeDelta::minutes(5),
+        )
+    }
+
+    fn solve(challenge: &str, difficulty: u8) -> String {
+        (0u64..)
+            .map(|n| n.to_string())
+            .find(|solution| {
+                let digest = Sha256::digest(format!("{}:{}", challenge, solution));
+                leading_zero_bits(&digest) >= u32::from(difficulty)
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn leading_zero_bits_are_counted_across_bytes() {
+        assert_eq!(leading_zero_bits(&[0xff]), 0);
+        assert_eq!(leading_zero_bits(&[0x0f, 0x00]), 4);
+        assert_eq!(leading_zero_bits(&[0x00, 0x01]), 15);
+        assert_eq!(leading_zero_bits(&[0x00, 0x00]), 16);
+    }
+
+    #[test]
+    fn a_solved_challenge_is_accepted() {
+        let pow = proof_of_work();
+        let now = Utc::now();
+        let challenge = pow.issue_challenge(now);
+        let solution = solve(&challenge.challenge, challenge.difficulty);
+        assert_ok!(pow.verify(&challenge.challenge, &solution, now));
+    }
+
+    #[test]
+    fn a_wrong_solution_is_rejected() {
+        let pow = proof_of_work();
+        let now = Utc::now();
+        let challenge = pow.issue_challenge(now);
+        // Find a solution that does *not* meet the difficulty
+        let wrong = (0u64..)
+            .map(|n| n.to_string())
+            .find(|s| {
+                let digest = Sha256::digest(format!("{}:{}", challenge.challenge, s));
+                leading_zero_bits(&digest) < 8
+            })
+            .unwrap();
+        assert_err_eq!(
+            pow.verify(&challenge.challenge, &wrong, now),
+            ProofOfWorkError::WrongSolution
+        );
+    }
+
+    #[test]
+    fn an_expired_challenge_is_rejected() {
+        let pow = proof_of_work();
+        let now = Utc::now();
+        let challenge = pow.issue_challenge(now);
+        let solution = solve(&challenge.challenge, challenge.difficulty);
+        assert_err_eq!(
+            pow.verify(&challenge.challenge, &solution, now + TimeDelta::minutes(6)),
+            ProofOfWorkError::Expired
+        );
+    }
+
+    #[test]
+    fn a_challenge_signed_with_another_secret_is_rejected() {
+        let other = ProofOfWork::new(SecretString::from("another-key"), 8, TimeDelta::minutes(5));
+        let now = Utc::now();
+        let challenge = other.issue_challenge(now);
+        let solution = solve(&challenge.challenge, challenge.difficulty);
+        assert_err_eq!(
+            proof_of_work().verify(&challenge.challenge, &solution, now),
+            ProofOfWorkError::InvalidChallenge
+        );
+    }
+
+    #[test]
+    fn garbage_is_rejected() {
+        let now = Utc::now();
+        assert_err_eq!(
+            proof_of_work().verify("not-a-challenge!", "0", now),
+            ProofOfWorkError::InvalidChallenge
+        );
+        assert_err_eq!(
+            proof_of_work().verify("", "0", now),
+            ProofOfWorkError::InvalidChallenge
+        );
+    }
+}
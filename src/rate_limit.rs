@@ -0,0 +1,305 @@
+use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+use crate::domain::SubscriberEmail;
+use crate::problem_details::ProblemDetails;
+use crate::state::AppState;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, HeaderValue, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use lru::LruCache;
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How many keys a limiter keeps track of, to keep memory bounded whatever the traffic.
+const MAX_TRACKED_KEYS: usize = 100_000;
+
+/// An in-memory token bucket per key.
+///
+/// Every key starts with `capacity` tokens; a request takes one, and tokens come back
+/// at a steady pace of one per `refill_interval`.
+///
+/// Buckets live in the memory of each replica: with `n` of them behind a load balancer,
+/// a client gets up to `n` times the configured budget. Past `MAX_TRACKED_KEYS`, the key
+/// seen least recently is forgotten (it starts over with a full bucket if it comes back),
+/// so a flood of new keys costs constant time and memory instead of growing the map.
+pub struct RateLimiter<K> {
+    capacity: f64,
+    refill_interval: Duration,
+    buckets: Mutex<LruCache<K, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl<K: Hash + Eq> RateLimiter<K> {
+    pub fn new(settings: &TokenBucketSettings) -> Self {
+        Self::with_max_keys(settings, MAX_TRACKED_KEYS)
+    }
+
+    fn with_max_keys(settings: &TokenBucketSettings, max_keys: usize) -> Self {
+        let max_keys = NonZeroUsize::new(max_keys).expect("A limiter must track at least one key");
+        Self {
+            capacity: f64::from(settings.capacity),
+            refill_interval: Duration::from_millis(settings.refill_interval_milliseconds),
+            buckets: Mutex::new(LruCache::new(max_keys)),
+        }
+    }
+
+    /// Take a token for `key`, or return how long to wait until one is available.
+    pub fn check(&self, key: K) -> Result<(), Duration> {
+        self.check_at(key, Instant::now())
+    }
+
+    fn check_at(&self, key: K, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        // Every check refills the bucket it touches: the least recently used one is also the
+        // one refilled the longest ago, and the first to be evicted.
+        let bucket = buckets.get_or_insert_mut(key, || Bucket {
+            tokens: self.capacity,
+            last_refill: now,
+        });
+        let tokens = self.refill(bucket, now);
+        bucket.tokens = tokens;
+        bucket.last_refill = now;
+        if tokens >= 1. {
+            bucket.tokens -= 1.;
+            Ok(())
+        } else {
+            Err(self.refill_interval.mul_f64(1. - tokens))
+        }
+    }
+
+    /// Tokens in the bucket at `now`, capped at `capacity`.
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let refilled = elapsed.as_secs_f64() / self.refill_interval.as_secs_f64();
+        (bucket.tokens + refilled).min(self.capacity)
+    }
+}
+
+/// Limits on `POST /subscriptions`: each one can trigger a confirmation email.
+#[derive(Clone)]
+pub struct SubscriptionRateLimiter {
+    per_ip: Arc<RateLimiter<IpAddr>>,
+    per_email: Arc<RateLimiter<String>>,
+    trusted_proxies: Arc<Vec<IpAddr>>,
+}
+
+impl SubscriptionRateLimiter {
+    pub fn new(settings: &RateLimitSettings) -> Self {
+        Self {
+            per_ip: Arc::new(RateLimiter::new(&settings.per_ip)),
+            per_email: Arc::new(RateLimiter::new(&settings.per_email)),
+            trusted_proxies: Arc::new(settings.trusted_proxies.clone()),
+        }
+    }
+
+    /// Take a token for `email`, whatever the case it is spelled in.
+    ///
+    /// Left to the handler, once the request got past the bot checks and validation:
+    /// junk requests, sent from as many IPs as it takes, must not use up the budget of
+    /// someone else's address and lock them out of subscribing.
+    pub fn check_email(&self, email: &SubscriberEmail) -> Result<(), Duration> {
+        self.per_email.check(email.as_ref().to_lowercase())
+    }
+
+    /// The address of the client, as reported by our own reverse proxies.
+    ///
+    /// `X-Forwarded-For` is only trusted when the request comes from one of them.
+    /// The header is read right to left: the first address we do not trust is the client,
+    /// anything further left could have been forged by it.
+    /// We fall back to the peer if we meet an address we cannot parse before that.
+    fn client_ip(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.trusted_proxies.contains(&peer) {
+            return peer;
+        }
+        let mut hops = Vec::new();
+        for value in headers.get_all("X-Forwarded-For") {
+            let Ok(value) = value.to_str() else {
+                return peer;
+            };
+            hops.extend(value.split(','));
+        }
+        for hop in hops.into_iter().rev() {
+            // Skipping a hop we cannot read, e.g. `ip:port`, would let the client pick its address
+            let Ok(address) = hop.trim().parse::<IpAddr>() else {
+                return peer;
+            };
+            if !self.trusted_proxies.contains(&address) {
+                return address;
+            }
+        }
+        peer
+    }
+}
+
+/// Reject subscription requests with `429 Too Many Requests` once a client IP
+/// has used up its budget.
+pub async fn rate_limit_subscriptions(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.subscription_rate_limiter;
+    let client_ip = limiter.client_ip(peer.ip(), request.headers());
+    if let Err(retry_after) = limiter.per_ip.check(client_ip) {
+        tracing::warn!(%client_ip, "Too many subscription requests from a single IP");
+        return too_many_requests(retry_after);
+    }
+    next.run(request).await
+}
+
+pub(crate) fn too_many_requests(retry_after: Duration) -> Response {
+    // `Retry-After` is in whole seconds: round up, so that retrying on time succeeds.
+    let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    let mut response = ProblemDetails::too_many_requests().into_response();
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimiter, SubscriptionRateLimiter};
+    use crate::configuration::{RateLimitSettings, TokenBucketSettings};
+    use axum::http::{HeaderMap, HeaderValue};
+    use claims::{assert_err, assert_ok};
+    use std::net::IpAddr;
+    use std::time::{Duration, Instant};
+
+    fn limiter(capacity: u32, refill_interval_milliseconds: u64) -> RateLimiter<&'static str> {
+        RateLimiter::new(&TokenBucketSettings {
+            capacity,
+            refill_interval_milliseconds,
+        })
+    }
+
+    #[test]
+    fn a_burst_up_to_capacity_is_allowed() {
+        let limiter = limiter(3, 1000);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert_ok!(limiter.check_at("key", now));
+        }
+        assert_err!(limiter.check_at("key", now));
+    }
+
+    #[test]
+    fn retry_after_is_the_time_until_the_next_token() {
+        let limiter = limiter(1, 1000);
+        let now = Instant::now();
+        assert_ok!(limiter.check_at("key", now));
+        let retry_after = limiter
+            .check_at("key", now + Duration::from_millis(250))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_millis(750));
+    }
+
+    #[test]
+    fn tokens_are_refilled_over_time_up_to_capacity() {
+        let limiter = limiter(2, 1000);
+        let now = Instant::now();
+        assert_ok!(limiter.check_at("key", now));
+        assert_ok!(limiter.check_at("key", now));
+        assert_err!(limiter.check_at("key", now));
+
+        let later = now + Duration::from_secs(60);
+        assert_ok!(limiter.check_at("key", later));
+        assert_ok!(limiter.check_at("key", later));
+        assert_err!(limiter.check_at("key", later));
+    }
+
+    #[test]
+    fn keys_have_their_own_bucket() {
+        let limiter = limiter(1, 1000);
+        let now = Instant::now();
+        assert_ok!(limiter.check_at("a", now));
+        assert_err!(limiter.check_at("a", now));
+        assert_ok!(limiter.check_at("b", now));
+    }
+
+    #[test]
+    fn the_least_recently_seen_keys_are_forgotten_first() {
+        let settings = TokenBucketSettings {
+            capacity: 1,
+            refill_interval_milliseconds: 1000,
+        };
+        let limiter = RateLimiter::with_max_keys(&settings, 2);
+        let now = Instant::now();
+        assert_ok!(limiter.check_at("a", now));
+        assert_ok!(limiter.check_at("b", now));
+        assert_err!(limiter.check_at("a", now));
+        // "b" is now the least recently seen key: "c" takes its place
+        assert_ok!(limiter.check_at("c", now));
+
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 2);
+        assert_err!(limiter.check_at("a", now));
+        assert_ok!(limiter.check_at("b", now));
+    }
+
+    fn subscription_limiter(trusted_proxies: &[&str]) -> SubscriptionRateLimiter {
+        let bucket = TokenBucketSettings {
+            capacity: 1,
+            refill_interval_milliseconds: 1000,
+        };
+        SubscriptionRateLimiter::new(&RateLimitSettings {
+            trusted_proxies: trusted_proxies
+                .iter()
+                .map(|ip| ip.parse().unwrap())
+                .collect(),
+            per_ip: bucket.clone(),
+            per_email: bucket,
+        })
+    }
+
+    fn forwarded_for(value: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert("X-Forwarded-For", HeaderValue::from_static(value));
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_from_untrusted_peers() {
+        let limiter = subscription_limiter(&["10.0.0.1"]);
+        let client_ip = limiter.client_ip(ip("203.0.113.7"), &forwarded_for("198.51.100.1"));
+        assert_eq!(client_ip, ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn forwarded_for_is_read_right_to_left_through_trusted_proxies() {
+        let limiter = subscription_limiter(&["10.0.0.1", "10.0.0.2"]);
+        let client_ip = limiter.client_ip(
+            ip("10.0.0.1"),
+            &forwarded_for("192.0.2.66, 198.51.100.1, 10.0.0.2"),
+        );
+        // 192.0.2.66 was added by the client itself: it cannot be trusted
+        assert_eq!(client_ip, ip("198.51.100.1"));
+    }
+
+    #[test]
+    fn a_trusted_peer_without_forwarded_for_is_the_client() {
+        let limiter = subscription_limiter(&["10.0.0.1"]);
+        let client_ip = limiter.client_ip(ip("10.0.0.1"), &HeaderMap::new());
+        assert_eq!(client_ip, ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn forwarded_for_stops_at_the_first_hop_it_cannot_parse() {
+        let limiter = subscription_limiter(&["10.0.0.1"]);
+        let client_ip =
+            limiter.client_ip(ip("10.0.0.1"), &forwarded_for("6.6.6.6, 203.0.113.9:4711"));
+        // 6.6.6.6 could have been forged: better rate limit the proxy than trust it
+        assert_eq!(client_ip, ip("10.0.0.1"));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::authentication::UserId;
+use crate::state::AppState;
+use crate::utils::e500;
+use anyhow::Context;
+use axum::extract::{Extension, State};
+use axum::response::{Html, Response};
+use sqlx::{Pool, Postgres};
+use uuid::Uuid;
+
+pub async fn admin_dashboard(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, Response> {
+    let username = get_username(*user_id, &state.db).await.map_err(e500)?;
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Admin dashboard</title>
+</head>
+<body>
+    <p>Welcome {}!</p>
+    <p>Available actions:</p>
+    <ol>
+        <li>
+            <form name="logoutForm" action="/admin/logout" method="post">
+                <input type="submit" value="Logout">
+            </form>
+        </li>
+    </ol>
+</body>
+</html>"#,
+        htmlescape::encode_minimal(&username)
+    )))
+}
+
+#[tracing::instrument(name = "Get username", skip(db_pool))]
+pub async fn get_username(
+    user_id: Uuid,
+    db_pool: &Pool<Postgres>,
+) -> Result<String, anyhow::Error> {
+    let row = sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id,)
+        .fetch_one(db_pool)
+        .await
+        .context("Failed to perform a query to retrieve a username.")?;
+    Ok(row.username)
+}
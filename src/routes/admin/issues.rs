@@ -0,0 +1,451 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailError;
+use crate::newsletter_issues::{
+    self, Content, IssueStatus, NewsletterIssue, fetch_issue, send_issue,
+};
+use crate::problem_details::{InvalidParam, ProblemDetails};
+use crate::routes::unsubscribe_link;
+use crate::state::AppState;
+use crate::templates::RenderedEmail;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::Json;
+use axum::extract::{Extension, Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct IssueData {
+    title: String,
+    content: Content,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueList {
+    issues: Vec<IssueSummary>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    published_at: Option<DateTime<Utc>>,
+    scheduled_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ScheduleData {
+    /// RFC 3339, with any offset, e.g. `2026-06-08T09:00:00+02:00`.
+    scheduled_at: DateTime<Utc>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewParameters {
+    /// Preview the issue the way subscribers with this locale get it.
+    locale: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestSendData {
+    /// Some of the configured test recipients, or all of them if missing.
+    recipients: Option<Vec<String>>,
+}
+
+#[derive(thiserror::Error)]
+pub enum IssueError {
+    #[error("Your request parameters didn't validate.")]
+    ValidationError(Vec<InvalidParam>),
+    #[error("There is no such issue.")]
+    NotFound,
+    #[error("The issue was sent already.")]
+    AlreadySent,
+    #[error("The issue is not scheduled.")]
+    NotScheduled,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for IssueError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for IssueError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::ValidationError(invalid_params) => {
+                tracing::warn!(?invalid_params, "Rejected an invalid issue request");
+                ProblemDetails::validation_error(invalid_params).into_response()
+            }
+            Self::NotFound => {
+                tracing::warn!(error = ?self, "Rejected an issue request");
+                StatusCode::NOT_FOUND.into_response()
+            }
+            Self::AlreadySent | Self::NotScheduled => {
+                tracing::warn!(error = ?self, "Rejected an issue request");
+                StatusCode::CONFLICT.into_response()
+            }
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to manage newsletter issues");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Every issue, drafts included, most recently updated first.
+#[tracing::instrument(name = "List newsletter issues", skip(state))]
+pub async fn list_issues(State(state): State<AppState>) -> Result<Json<IssueList>, IssueError> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT newsletter_issue_id, title, status, created_at, updated_at, published_at,
+            scheduled_at
+        FROM newsletter_issues
+        ORDER BY updated_at DESC, newsletter_issue_id
+        "#
+    )
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to fetch newsletter issues.")?;
+    Ok(Json(IssueList { issues }))
+}
+
+/// Start a new issue, as a draft: nobody gets it until it is published or scheduled.
+#[tracing::instrument(
+    name = "Create a draft issue",
+    skip(state, body),
+    fields(admin_id = %user_id, newsletter_title = %body.title)
+)]
+pub async fn create_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Json(body): Json<IssueData>,
+) -> Result<(StatusCode, Json<NewsletterIssue>), IssueError> {
+    let content = body.content.render();
+    let issue =
+        newsletter_issues::insert_issue(&state.db, &body.title, &content, IssueStatus::Draft)
+            .await
+            .context("Failed to store the draft issue.")?;
+    Ok((StatusCode::CREATED, Json(issue)))
+}
+
+#[tracing::instrument(name = "Get a newsletter issue", skip(state))]
+pub async fn get_issue(
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<NewsletterIssue>, IssueError> {
+    Ok(Json(find_issue(&state, issue_id).await?))
+}
+
+/// Replace the title and content of an issue, until it is sent.
+#[tracing::instrument(
+    name = "Update an issue",
+    skip(state, body),
+    fields(admin_id = %user_id, newsletter_title = %body.title)
+)]
+pub async fn update_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<IssueData>,
+) -> Result<Json<NewsletterIssue>, IssueError> {
+    let content = body.content.render();
+    let updated = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET title = $2, text_content = $3, html_content = $4, markdown_content = $5,
+            updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status <> 'sent'
+        RETURNING newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        "#,
+        issue_id,
+        body.title,
+        content.text,
+        content.html,
+        content.markdown,
+    )
+    .fetch_optional(&state.db)
+    .await
+    .context("Failed to update the issue.")?;
+    match updated {
+        Some(issue) => Ok(Json(issue)),
+        None => Err(missing_or(&state, issue_id, IssueError::AlreadySent).await),
+    }
+}
+
+/// Send an issue later on, or change when: it can still be edited until then.
+#[tracing::instrument(name = "Schedule an issue", skip(state, body), fields(admin_id = %user_id))]
+pub async fn schedule_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<ScheduleData>,
+) -> Result<Json<NewsletterIssue>, IssueError> {
+    if body.scheduled_at <= Utc::now() {
+        return Err(IssueError::ValidationError(vec![InvalidParam {
+            name: "scheduled_at",
+            code: "in_the_past".into(),
+            reason: "An issue can only be scheduled in the future.".into(),
+        }]));
+    }
+    // Waits for the scheduler if it is sending the issue right now, then finds it sent
+    let scheduled = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'scheduled', scheduled_at = $2, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status <> 'sent'
+        RETURNING newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        "#,
+        issue_id,
+        body.scheduled_at,
+    )
+    .fetch_optional(&state.db)
+    .await
+    .context("Failed to schedule the issue.")?;
+    match scheduled {
+        Some(issue) => {
+            tracing::info!(scheduled_at = %body.scheduled_at, "Scheduled an issue");
+            Ok(Json(issue))
+        }
+        None => Err(missing_or(&state, issue_id, IssueError::AlreadySent).await),
+    }
+}
+
+/// Turn a scheduled issue back into a draft, as long as it has not been sent.
+#[tracing::instrument(name = "Cancel a scheduled issue", skip(state), fields(admin_id = %user_id))]
+pub async fn cancel_scheduled_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<Json<NewsletterIssue>, IssueError> {
+    let cancelled = sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'draft', scheduled_at = NULL, updated_at = now()
+        WHERE newsletter_issue_id = $1 AND status = 'scheduled'
+        RETURNING newsletter_issue_id, title, status, text_content, html_content,
+            markdown_content, created_at, updated_at, published_at, scheduled_at
+        "#,
+        issue_id,
+    )
+    .fetch_optional(&state.db)
+    .await
+    .context("Failed to cancel the scheduled issue.")?;
+    match cancelled {
+        Some(issue) => {
+            tracing::info!("Cancelled a scheduled issue");
+            Ok(Json(issue))
+        }
+        None => Err(missing_or(&state, issue_id, IssueError::NotScheduled).await),
+    }
+}
+
+/// The email subscribers would get, in both formats.
+#[tracing::instrument(name = "Preview a newsletter issue", skip(state, parameters))]
+pub async fn preview_issue(
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+    Query(parameters): Query<PreviewParameters>,
+) -> Result<Json<RenderedEmail>, IssueError> {
+    let issue = find_issue(&state, issue_id).await?;
+    Ok(Json(render_issue(
+        &state,
+        &issue,
+        parameters.locale.as_deref(),
+    )?))
+}
+
+/// Mail an issue, whatever its state, to some of the configured test recipients only.
+///
+/// The subject is prefixed with `[Test]` so that the email is not mistaken for the real one.
+#[tracing::instrument(name = "Send a test issue", skip(state, body), fields(admin_id = %user_id))]
+pub async fn send_test_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+    Json(body): Json<TestSendData>,
+) -> Result<StatusCode, IssueError> {
+    let recipients = test_recipients(&state.test_recipients, body.recipients)?;
+    let issue = find_issue(&state, issue_id).await?;
+    let email = render_issue(&state, &issue, None)?;
+    let subject = format!("[Test] {}", email.subject);
+    for recipient in recipients {
+        match state
+            .email_client
+            .send_email(recipient, &subject, &email.html, &email.text)
+            .await
+        {
+            Ok(()) => {}
+            Err(EmailError::Suppressed) => {
+                tracing::info!("Skipping a test recipient on the suppression list.");
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context("Failed to send a test issue.")
+                    .into());
+            }
+        }
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Send an issue to every confirmed subscriber now, even if it was scheduled for later.
+#[tracing::instrument(name = "Publish an issue", skip(state), fields(admin_id = %user_id))]
+pub async fn publish_issue(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(issue_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<NewsletterIssue>), IssueError> {
+    let mut transaction = state
+        .db
+        .begin()
+        .await
+        .context("Failed to start a transaction.")?;
+    let Some(issue) = send_issue(&mut transaction, issue_id)
+        .await
+        .context("Failed to publish the issue.")?
+    else {
+        return Err(missing_or(&state, issue_id, IssueError::AlreadySent).await);
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the published issue.")?;
+    tracing::info!("Published an issue");
+    // Delivery happens in the background worker
+    Ok((StatusCode::ACCEPTED, Json(issue)))
+}
+
+/// Why an issue could not be changed: it does not exist, or `error` otherwise.
+async fn missing_or(state: &AppState, issue_id: Uuid, error: IssueError) -> IssueError {
+    match find_issue(state, issue_id).await {
+        Ok(_) => error,
+        Err(e) => e,
+    }
+}
+
+async fn find_issue(state: &AppState, issue_id: Uuid) -> Result<NewsletterIssue, IssueError> {
+    fetch_issue(&state.db, issue_id)
+        .await
+        .context("Failed to fetch the newsletter issue.")?
+        .ok_or(IssueError::NotFound)
+}
+
+/// Wrap the issue the way the delivery worker does.
+fn render_issue(
+    state: &AppState,
+    issue: &NewsletterIssue,
+    locale: Option<&str>,
+) -> Result<RenderedEmail, anyhow::Error> {
+    // A link for nobody: it looks like the real thing, but unsubscribes no one
+    let link = unsubscribe_link(&state.base_url, Uuid::nil(), &state.hmac_secret)
+        .context("Failed to build the unsubscribe link")?;
+    Ok(state.email_templates.newsletter(
+        locale,
+        &issue.title,
+        &issue.html_content,
+        &issue.text_content,
+        &link,
+    ))
+}
+
+/// The `requested` addresses, which must all be allowed, or every allowed one.
+fn test_recipients(
+    allowed: &[SubscriberEmail],
+    requested: Option<Vec<String>>,
+) -> Result<Vec<SubscriberEmail>, IssueError> {
+    let recipients = match requested {
+        None => allowed.to_vec(),
+        Some(requested) => {
+            let mut recipients = Vec::new();
+            for email in requested {
+                let recipient = allowed
+                    .iter()
+                    .find(|allowed| allowed.as_ref().eq_ignore_ascii_case(&email))
+                    .ok_or_else(|| {
+                        IssueError::ValidationError(vec![InvalidParam {
+                            name: "recipients",
+                            code: "not_a_test_recipient".into(),
+                            reason: format!(
+                                "{} is not in `newsletter_issues.test_recipients`.",
+                                email
+                            ),
+                        }])
+                    })?;
+                recipients.push(recipient.clone());
+            }
+            recipients
+        }
+    };
+    if recipients.is_empty() {
+        return Err(IssueError::ValidationError(vec![InvalidParam {
+            name: "recipients",
+            code: "empty".into(),
+            reason: "There is nobody to send the test to.".into(),
+        }]));
+    }
+    Ok(recipients)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IssueError, test_recipients};
+    use crate::domain::SubscriberEmail;
+    use claims::{assert_matches, assert_ok};
+
+    fn allowed() -> Vec<SubscriberEmail> {
+        ["editor@example.com", "owner@example.com"]
+            .into_iter()
+            .map(|email| SubscriberEmail::parse(email.into()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sends_go_to_every_allowed_address_by_default() {
+        let recipients = assert_ok!(test_recipients(&allowed(), None));
+
+        assert_eq!(recipients.len(), 2);
+    }
+
+    #[test]
+    fn test_sends_only_go_to_allowed_addresses() {
+        let recipients = assert_ok!(test_recipients(
+            &allowed(),
+            Some(vec!["Editor@Example.com".into()])
+        ));
+        assert_eq!(recipients.len(), 1);
+        assert_eq!(recipients[0].as_ref(), "editor@example.com");
+
+        assert_matches!(
+            test_recipients(
+                &allowed(),
+                Some(vec![
+                    "editor@example.com".into(),
+                    "someone@example.com".into()
+                ])
+            ),
+            Err(IssueError::ValidationError(_))
+        );
+        assert_matches!(
+            test_recipients(&[], None),
+            Err(IssueError::ValidationError(_))
+        );
+        assert_matches!(
+            test_recipients(&allowed(), Some(vec![])),
+            Err(IssueError::ValidationError(_))
+        );
+    }
+}
@@ -0,0 +1,13 @@
+use crate::session_state::TypedSession;
+use crate::utils::e500;
+use axum::response::{Redirect, Response};
+
+#[tracing::instrument(name = "Log out", skip(session))]
+pub async fn log_out(session: TypedSession) -> Result<Redirect, Response> {
+    session.log_out().await.map_err(e500)?;
+    session
+        .insert_flash("You have successfully logged out.")
+        .await
+        .map_err(e500)?;
+    Ok(Redirect::to("/login"))
+}
@@ -0,0 +1,18 @@
+mod dashboard;
+mod issues;
+mod logout;
+mod subscribers;
+mod subscribers_export;
+mod subscribers_import;
+mod suppressions;
+
+pub use dashboard::admin_dashboard;
+pub use issues::{
+    cancel_scheduled_issue, create_issue, get_issue, list_issues, preview_issue, publish_issue,
+    schedule_issue, send_test_issue, update_issue,
+};
+pub use logout::log_out;
+pub use subscribers::list_subscribers;
+pub use subscribers_export::export_subscribers;
+pub use subscribers_import::{MAX_IMPORT_SIZE, import_subscribers};
+pub use suppressions::{add_suppression, list_suppressions, remove_suppression};
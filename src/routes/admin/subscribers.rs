@@ -0,0 +1,253 @@
+use crate::problem_details::{InvalidParam, ProblemDetails};
+use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Every value the `subscriptions.status` column can hold.
+pub const SUBSCRIBER_STATUSES: &[&str] = &[
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 500;
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ListSubscribersParameters {
+    status: Option<String>,
+    /// Case-insensitive substring of the email address or of the name.
+    search: Option<String>,
+    /// Inclusive.
+    subscribed_after: Option<DateTime<Utc>>,
+    /// Exclusive.
+    subscribed_before: Option<DateTime<Utc>>,
+    limit: Option<i64>,
+    /// `next_cursor` from the previous page.
+    cursor: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscribersPage {
+    subscribers: Vec<Subscriber>,
+    /// Subscribers matching the filters, across all pages.
+    total: i64,
+    /// `None` on the last page.
+    next_cursor: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+/// Position in the listing: the `(subscribed_at, id)` of the last subscriber on a page.
+///
+/// Unlike an offset, it stays correct while subscribers are added or removed.
+#[derive(Debug, PartialEq)]
+struct Cursor {
+    subscribed_at: DateTime<Utc>,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn encode(&self) -> String {
+        // Postgres stores timestamps with microsecond precision: nothing is lost
+        let mut payload = self.subscribed_at.timestamp_micros().to_be_bytes().to_vec();
+        payload.extend_from_slice(self.id.as_bytes());
+        URL_SAFE_NO_PAD.encode(payload)
+    }
+
+    fn decode(cursor: &str) -> Option<Self> {
+        let payload = URL_SAFE_NO_PAD.decode(cursor).ok()?;
+        if payload.len() != 24 {
+            return None;
+        }
+        let (micros, id) = payload.split_at(8);
+        let subscribed_at =
+            DateTime::from_timestamp_micros(i64::from_be_bytes(micros.try_into().ok()?))?;
+        let id = Uuid::from_slice(id).ok()?;
+        Some(Self { subscribed_at, id })
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum ListSubscribersError {
+    #[error("`{0}` is not a subscriber status.")]
+    InvalidStatus(String),
+    #[error("`limit` must be between 1 and {MAX_PAGE_SIZE}.")]
+    InvalidLimit,
+    #[error("`cursor` was not returned by a previous request.")]
+    InvalidCursor,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ListSubscribersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ListSubscribersError {
+    fn into_response(self) -> Response {
+        let (name, code) = match self {
+            Self::InvalidStatus(_) => ("status", "invalid_status"),
+            Self::InvalidLimit => ("limit", "out_of_range"),
+            Self::InvalidCursor => ("cursor", "invalid_cursor"),
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to list subscribers");
+                return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+            }
+        };
+        tracing::warn!(error = ?self, "Rejected a request to list subscribers");
+        ProblemDetails::validation_error(vec![InvalidParam {
+            name,
+            code: code.into(),
+            reason: self.to_string(),
+        }])
+        .into_response()
+    }
+}
+
+/// List subscribers, most recent first, one page at a time.
+#[tracing::instrument(name = "List subscribers", skip(state))]
+pub async fn list_subscribers(
+    State(state): State<AppState>,
+    Query(parameters): Query<ListSubscribersParameters>,
+) -> Result<Json<SubscribersPage>, ListSubscribersError> {
+    if let Some(status) = &parameters.status
+        && !SUBSCRIBER_STATUSES.contains(&status.as_str())
+    {
+        return Err(ListSubscribersError::InvalidStatus(status.clone()));
+    }
+    let limit = parameters.limit.unwrap_or(DEFAULT_PAGE_SIZE);
+    if !(1..=MAX_PAGE_SIZE).contains(&limit) {
+        return Err(ListSubscribersError::InvalidLimit);
+    }
+    let cursor = parameters
+        .cursor
+        .as_deref()
+        .map(|cursor| Cursor::decode(cursor).ok_or(ListSubscribersError::InvalidCursor))
+        .transpose()?;
+    let search = parameters
+        .search
+        .as_deref()
+        .map(str::trim)
+        .filter(|search| !search.is_empty())
+        .map(|search| format!("%{}%", escape_like(search)));
+
+    // Fetch one extra row to know whether there is a next page.
+    let mut subscribers = sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+          AND ($2::text IS NULL OR email ILIKE $2 OR name ILIKE $2)
+          AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+          AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+          AND ($5::timestamptz IS NULL OR (subscribed_at, id) < ($5, $6))
+        ORDER BY subscribed_at DESC, id DESC
+        LIMIT $7
+        "#,
+        parameters.status,
+        search,
+        parameters.subscribed_after,
+        parameters.subscribed_before,
+        cursor.as_ref().map(|c| c.subscribed_at),
+        cursor.as_ref().map(|c| c.id),
+        limit + 1
+    )
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to fetch subscribers.")?;
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "total!"
+        FROM subscriptions
+        WHERE ($1::text IS NULL OR status = $1)
+          AND ($2::text IS NULL OR email ILIKE $2 OR name ILIKE $2)
+          AND ($3::timestamptz IS NULL OR subscribed_at >= $3)
+          AND ($4::timestamptz IS NULL OR subscribed_at < $4)
+        "#,
+        parameters.status,
+        search,
+        parameters.subscribed_after,
+        parameters.subscribed_before,
+    )
+    .fetch_one(&state.db)
+    .await
+    .context("Failed to count subscribers.")?;
+
+    let next_cursor = if subscribers.len() as i64 > limit {
+        subscribers.truncate(limit as usize);
+        subscribers.last().map(|last| {
+            Cursor {
+                subscribed_at: last.subscribed_at,
+                id: last.id,
+            }
+            .encode()
+        })
+    } else {
+        None
+    };
+    Ok(Json(SubscribersPage {
+        subscribers,
+        total,
+        next_cursor,
+    }))
+}
+
+/// Match `search` literally in a `LIKE` pattern.
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cursor, escape_like};
+    use chrono::{DateTime, Utc};
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    #[test]
+    fn a_cursor_survives_a_round_trip() {
+        let cursor = Cursor {
+            subscribed_at: DateTime::<Utc>::from_timestamp_micros(1_767_225_600_123_456).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        let encoded = cursor.encode();
+        assert_some_eq!(Cursor::decode(&encoded), cursor);
+    }
+
+    #[test]
+    fn garbage_is_not_a_cursor() {
+        assert_none!(Cursor::decode("not a cursor"));
+        assert_none!(Cursor::decode(""));
+        assert_none!(Cursor::decode("AAAA"));
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("ursula"), "ursula");
+        assert_eq!(escape_like("100%_real\\"), "100\\%\\_real\\\\");
+    }
+}
@@ -0,0 +1,336 @@
+use crate::authentication::UserId;
+use crate::domain::NewSubscriber;
+use crate::extractors::content_type;
+use crate::problem_details::{InvalidParam, ProblemDetails};
+use crate::routes::parse_new_subscriber;
+use crate::state::AppState;
+use crate::suppressions::is_suppressed;
+use crate::utils::error_chain_fmt;
+use axum::Json;
+use axum::extract::{Extension, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Largest CSV file we take, about 100,000 subscribers: well past axum's default of 2 MB.
+pub const MAX_IMPORT_SIZE: usize = 10 * 1024 * 1024;
+
+/// The status imported subscribers end up in.
+#[derive(serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    /// They get a confirmation email, like after subscribing through the form.
+    /// Emails are queued for the background worker: the import does not wait for them.
+    Pending,
+    /// Consent was collected elsewhere, e.g. by the tool we migrate from: no email is sent.
+    Confirmed,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct ImportParameters {
+    status: ImportStatus,
+}
+
+#[derive(serde::Deserialize)]
+struct ImportRow {
+    email: String,
+    name: String,
+    /// When consent was given, if the source knows. Defaults to the time of the import.
+    #[serde(default)]
+    consented_at: Option<DateTime<Utc>>,
+    /// e.g. `fr`. Defaults to the default locale, as do those we have no catalog for.
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+#[derive(serde::Serialize, Default)]
+pub struct ImportReport {
+    /// Rows that created or updated a subscriber.
+    imported: usize,
+    /// Valid rows left alone, e.g. someone who unsubscribed.
+    skipped: Vec<RowReport>,
+    /// Rows that could not be imported.
+    errors: Vec<RowReport>,
+}
+
+#[derive(serde::Serialize)]
+pub struct RowReport {
+    /// 1-based, counting the header.
+    line: u64,
+    #[serde(rename = "invalid-params")]
+    invalid_params: Vec<InvalidParam>,
+}
+
+impl RowReport {
+    fn new(line: u64, name: &'static str, code: &str, reason: impl Into<String>) -> Self {
+        Self {
+            line,
+            invalid_params: vec![InvalidParam {
+                name,
+                code: code.into(),
+                reason: reason.into(),
+            }],
+        }
+    }
+}
+
+enum RowOutcome {
+    Imported { subscriber_id: Uuid },
+    Skipped { status: String },
+}
+
+#[derive(thiserror::Error)]
+pub enum ImportError {
+    #[error("The body must be `text/csv`.")]
+    UnsupportedMediaType,
+    #[error("The CSV header must name an `email` and a `name` column.")]
+    MissingColumns,
+    #[error("Failed to store the imported subscribers in the database.")]
+    StoreError(#[from] sqlx::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ImportError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnsupportedMediaType => {
+                tracing::warn!(error = ?self, "Rejected a subscriber import");
+                StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response()
+            }
+            Self::MissingColumns => {
+                tracing::warn!(error = ?self, "Rejected a subscriber import");
+                ProblemDetails::validation_error(vec![InvalidParam {
+                    name: "header",
+                    code: "missing_columns".into(),
+                    reason: self.to_string(),
+                }])
+                .into_response()
+            }
+            Self::StoreError(_) | Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to import subscribers");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Create or update subscribers from a CSV file with `email` and `name` columns.
+///
+/// Every row is validated like a subscription through the form; valid rows are imported
+/// even if others are not, and the report lists what happened to the rest.
+/// Subscribers who are already confirmed, or who opted out, are never changed,
+/// and suppressed addresses are never imported.
+#[tracing::instrument(name = "Import subscribers", skip(state, headers, body), fields(admin_id = %user_id))]
+pub async fn import_subscribers(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Query(parameters): Query<ImportParameters>,
+    headers: HeaderMap,
+    body: String,
+) -> Result<Json<ImportReport>, ImportError> {
+    if content_type(&headers).as_deref() != Some("text/csv") {
+        return Err(ImportError::UnsupportedMediaType);
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(body.as_bytes());
+    let columns = reader
+        .headers()
+        .map_err(|_| ImportError::MissingColumns)?
+        .clone();
+    if !(columns.iter().any(|c| c == "email") && columns.iter().any(|c| c == "name")) {
+        return Err(ImportError::MissingColumns);
+    }
+
+    let mut report = ImportReport::default();
+    let mut seen_emails = HashSet::new();
+    let now = Utc::now();
+    let mut transaction = state.db.begin().await?;
+    let mut record = csv::StringRecord::new();
+    loop {
+        let line = match reader.read_record(&mut record) {
+            Ok(false) => break,
+            Ok(true) => record.position().map_or(0, |p| p.line()),
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line());
+                report
+                    .errors
+                    .push(RowReport::new(line, "row", "malformed", e.to_string()));
+                continue;
+            }
+        };
+        let row = match record.deserialize::<ImportRow>(Some(&columns)) {
+            Ok(row) => row,
+            Err(e) => {
+                report
+                    .errors
+                    .push(RowReport::new(line, "row", "malformed", e.to_string()));
+                continue;
+            }
+        };
+        let consented_at = row.consented_at.unwrap_or(now);
+        let locale = state
+            .email_templates
+            .negotiate_locale(row.locale.as_deref());
+        let new_subscriber = match parse_new_subscriber(row.name, row.email) {
+            Ok(new_subscriber) => new_subscriber,
+            Err(errors) => {
+                report.errors.push(RowReport {
+                    line,
+                    invalid_params: errors.iter().flat_map(|e| e.invalid_params()).collect(),
+                });
+                continue;
+            }
+        };
+        if !seen_emails.insert(new_subscriber.email.as_ref().to_lowercase()) {
+            report.errors.push(RowReport::new(
+                line,
+                "email",
+                "duplicate",
+                "The email address already appears on an earlier line.",
+            ));
+            continue;
+        }
+        // Importing them would only put them back on a list we can never send to.
+        if is_suppressed(&mut *transaction, new_subscriber.email.as_ref()).await? {
+            report.skipped.push(RowReport::new(
+                line,
+                "email",
+                "suppressed",
+                "The email address is on the suppression list.",
+            ));
+            continue;
+        }
+
+        match import_subscriber(
+            &mut transaction,
+            &new_subscriber,
+            parameters.status,
+            consented_at,
+            &locale,
+        )
+        .await?
+        {
+            RowOutcome::Imported { subscriber_id } => {
+                report.imported += 1;
+                if parameters.status == ImportStatus::Pending {
+                    enqueue_confirmation(&mut transaction, subscriber_id).await?;
+                }
+            }
+            RowOutcome::Skipped { status } => report.skipped.push(RowReport::new(
+                line,
+                "email",
+                &format!("already_{}", status),
+                format!("The subscriber is already `{}`.", status),
+            )),
+        }
+    }
+    transaction.commit().await?;
+
+    report.errors.sort_by_key(|row| row.line);
+    tracing::info!(
+        imported = report.imported,
+        skipped = report.skipped.len(),
+        errors = report.errors.len(),
+        "Imported subscribers"
+    );
+    Ok(Json(report))
+}
+
+/// The delivery worker sends the email, and the token it carries, once the import is committed.
+async fn enqueue_confirmation(
+    transaction: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id)
+        VALUES ($1)
+        ON CONFLICT (subscriber_id) DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(())
+}
+
+/// Insert a new subscriber in the requested status, or update one still waiting for confirmation.
+#[tracing::instrument(
+    name = "Import a subscriber in the database",
+    skip(transaction, new_subscriber, consented_at)
+)]
+async fn import_subscriber(
+    transaction: &mut sqlx::Transaction<'static, sqlx::Postgres>,
+    new_subscriber: &NewSubscriber,
+    status: ImportStatus,
+    consented_at: DateTime<Utc>,
+    locale: &str,
+) -> Result<RowOutcome, sqlx::Error> {
+    // Consent is only recorded when the admin vouches for it.
+    let (status, consented_at, consent_source) = match status {
+        ImportStatus::Pending => ("pending_confirmation", None, None),
+        ImportStatus::Confirmed => ("confirmed", Some(consented_at), Some("import")),
+    };
+    let existing = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    match existing {
+        None => {
+            let subscriber_id = Uuid::new_v4();
+            sqlx::query!(
+                r#"
+                INSERT INTO subscriptions (
+                    id, email, name, subscribed_at, status, consented_at, consent_source, locale
+                )
+                VALUES ($1, $2, $3, now(), $4, $5, $6, $7)
+                "#,
+                subscriber_id,
+                new_subscriber.email.as_ref(),
+                new_subscriber.name.as_ref(),
+                status,
+                consented_at,
+                consent_source,
+                locale
+            )
+            .execute(&mut **transaction)
+            .await?;
+            Ok(RowOutcome::Imported { subscriber_id })
+        }
+        Some(existing) if existing.status == "pending_confirmation" => {
+            sqlx::query!(
+                r#"
+                UPDATE subscriptions
+                SET name = $2, status = $3, consented_at = $4, consent_source = $5, locale = $6
+                WHERE id = $1
+                "#,
+                existing.id,
+                new_subscriber.name.as_ref(),
+                status,
+                consented_at,
+                consent_source,
+                locale
+            )
+            .execute(&mut **transaction)
+            .await?;
+            Ok(RowOutcome::Imported {
+                subscriber_id: existing.id,
+            })
+        }
+        Some(existing) => Ok(RowOutcome::Skipped {
+            status: existing.status,
+        }),
+    }
+}
@@ -0,0 +1,129 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::problem_details::ProblemDetails;
+use crate::routes::SubscriberError;
+use crate::state::AppState;
+use crate::suppressions::{self, Suppression, SuppressionReason};
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::Json;
+use axum::extract::{Extension, Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+#[derive(serde::Serialize)]
+pub struct SuppressionList {
+    suppressions: Vec<Suppression>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct SuppressionData {
+    email: String,
+    reason: SuppressionReason,
+}
+
+#[derive(thiserror::Error)]
+pub enum SuppressionError {
+    #[error(transparent)]
+    ValidationError(SubscriberError),
+    #[error("The email address is not on the suppression list.")]
+    NotFound,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SuppressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for SuppressionError {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::ValidationError(e) => {
+                tracing::warn!(error = ?self, "Rejected an invalid suppression");
+                ProblemDetails::validation_error(e.invalid_params()).into_response()
+            }
+            Self::NotFound => {
+                tracing::warn!(error = ?self, "Rejected a suppression removal");
+                StatusCode::NOT_FOUND.into_response()
+            }
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to manage suppressions");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Every suppressed address, most recent first.
+#[tracing::instrument(name = "List suppressions", skip(state))]
+pub async fn list_suppressions(
+    State(state): State<AppState>,
+) -> Result<Json<SuppressionList>, SuppressionError> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, source, created_at
+        FROM suppressions
+        ORDER BY created_at DESC, email
+        "#
+    )
+    .fetch_all(&state.db)
+    .await
+    .context("Failed to fetch suppressions.")?;
+    Ok(Json(SuppressionList { suppressions }))
+}
+
+/// Stop mailing an address, whether or not it belongs to a subscriber.
+///
+/// `201 Created` with the new entry, or `200 OK` with the existing one if the address
+/// was already suppressed: its original reason is kept.
+#[tracing::instrument(name = "Add a suppression", skip(state, body), fields(admin_id = %user_id))]
+pub async fn add_suppression(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Json(body): Json<SuppressionData>,
+) -> Result<(StatusCode, Json<Suppression>), SuppressionError> {
+    let email = SubscriberEmail::parse(body.email)
+        .map_err(|e| SuppressionError::ValidationError(SubscriberError::InvalidEmail(e)))?;
+    let created = suppressions::add_suppression(&state.db, email.as_ref(), body.reason, "admin")
+        .await
+        .context("Failed to store the suppression.")?;
+    if let Some(suppression) = created {
+        tracing::info!(reason = ?body.reason, "Suppressed an email address");
+        return Ok((StatusCode::CREATED, Json(suppression)));
+    }
+    let existing = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email, reason, source, created_at
+        FROM suppressions
+        WHERE email = lower($1)
+        "#,
+        email.as_ref()
+    )
+    .fetch_one(&state.db)
+    .await
+    .context("Failed to fetch the existing suppression.")?;
+    Ok((StatusCode::OK, Json(existing)))
+}
+
+/// Allow mailing an address again. It is not resubscribed: its owner has to do that.
+#[tracing::instrument(name = "Remove a suppression", skip(state, email), fields(admin_id = %user_id))]
+pub async fn remove_suppression(
+    Extension(user_id): Extension<UserId>,
+    State(state): State<AppState>,
+    Path(email): Path<String>,
+) -> Result<StatusCode, SuppressionError> {
+    let deleted = sqlx::query!(r#"DELETE FROM suppressions WHERE email = lower($1)"#, email)
+        .execute(&state.db)
+        .await
+        .context("Failed to remove the suppression.")?;
+    if deleted.rows_affected() == 0 {
+        return Err(SuppressionError::NotFound);
+    }
+    tracing::info!("Removed a suppression");
+    Ok(StatusCode::NO_CONTENT)
+}
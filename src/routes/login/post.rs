@@ -0,0 +1,77 @@
+use crate::authentication::{AuthError, Credentials, validate_credentials};
+use crate::session_state::TypedSession;
+use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::{
+    extract::{Form, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+};
+use secrecy::SecretString;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+    password: SecretString,
+}
+
+#[derive(thiserror::Error)]
+pub enum LoginError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for LoginError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for LoginError {
+    fn into_response(self) -> Response {
+        tracing::error!(error = ?self, "Failed to log a user in");
+        match self {
+            Self::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Log in",
+    skip(session, state, form),
+    fields(username = %form.username)
+)]
+pub async fn login(
+    session: TypedSession,
+    State(state): State<AppState>,
+    Form(form): Form<FormData>,
+) -> Result<Redirect, LoginError> {
+    let credentials = Credentials {
+        username: form.username,
+        password: form.password,
+    };
+    match validate_credentials(credentials, &state.db).await {
+        Ok(user_id) => {
+            // Rotate the session id on login to prevent session fixation attacks.
+            session
+                .renew()
+                .await
+                .context("Failed to renew the session")?;
+            session
+                .insert_user_id(user_id)
+                .await
+                .context("Failed to store the user id in the session")?;
+            Ok(Redirect::to("/admin/dashboard"))
+        }
+        Err(AuthError::InvalidCredentials(e)) => {
+            tracing::warn!(error.cause_chain = ?e, "Failed login attempt");
+            session
+                .insert_flash("Authentication failed.")
+                .await
+                .context("Failed to store the flash message in the session")?;
+            Ok(Redirect::to("/login"))
+        }
+        Err(AuthError::UnexpectedError(e)) => Err(LoginError::UnexpectedError(e)),
+    }
+}
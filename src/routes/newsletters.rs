@@ -0,0 +1,88 @@
+use crate::authentication::BasicAuthUser;
+use crate::idempotency::{IdempotencyKey, NextAction, save_response, try_processing};
+use crate::newsletter_issues::{Content, IssueStatus, enqueue_delivery_tasks, insert_issue};
+use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+
+#[derive(serde::Deserialize)]
+pub struct BodyData {
+    title: String,
+    content: Content,
+}
+
+#[derive(thiserror::Error)]
+pub enum PublishError {
+    #[error("Invalid idempotency key.")]
+    InvalidIdempotencyKey(#[source] anyhow::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PublishError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::InvalidIdempotencyKey(_) => {
+                tracing::warn!(error = ?self, "Rejected a newsletter issue");
+                StatusCode::BAD_REQUEST.into_response()
+            }
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to publish a newsletter issue");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(user, state, body),
+    fields(newsletter_title = %body.title, user_id = %user.user_id)
+)]
+pub async fn publish_newsletter(
+    user: BasicAuthUser,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(body): Json<BodyData>,
+) -> Result<Response, PublishError> {
+    let idempotency_key =
+        get_idempotency_key(&headers).map_err(PublishError::InvalidIdempotencyKey)?;
+    let mut transaction = match try_processing(&state.db, &idempotency_key, user.user_id).await? {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+    };
+
+    let content = body.content.render();
+    let issue = insert_issue(&mut *transaction, &body.title, &content, IssueStatus::Sent)
+        .await
+        .context("Failed to store newsletter issue details")?;
+    enqueue_delivery_tasks(&mut *transaction, issue.newsletter_issue_id)
+        .await
+        .context("Failed to enqueue delivery tasks")?;
+    // Delivery happens in the background worker: we only acknowledge that the issue was accepted.
+    let response = StatusCode::ACCEPTED.into_response();
+    let response = save_response(transaction, &idempotency_key, user.user_id, response).await?;
+    Ok(response)
+}
+
+fn get_idempotency_key(headers: &HeaderMap) -> Result<IdempotencyKey, anyhow::Error> {
+    let idempotency_key = headers
+        .get("Idempotency-Key")
+        .context("The 'Idempotency-Key' header was missing")?
+        .to_str()
+        .context("The 'Idempotency-Key' header was not a valid UTF8 string.")?
+        .to_owned();
+    idempotency_key.try_into()
+}
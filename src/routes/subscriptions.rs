@@ -1,49 +1,209 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberNameError, SubscriptionToken,
+};
+use crate::email_client::{EmailClient, EmailError};
+use crate::extractors::{FormOrJson, accepted_languages, accepts_json};
+use crate::problem_details::{InvalidParam, ProblemDetails};
+use crate::proof_of_work::{self, ProofOfWork, ProofOfWorkError, SolvedChallenge};
+use crate::rate_limit::too_many_requests;
 use crate::state::AppState;
+use crate::templates::EmailTemplates;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
 use axum::{
-    extract::{Form, State},
-    http::StatusCode,
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
 };
-use chrono::Utc;
-use rand::distr::Alphanumeric;
-use rand::{Rng, rng};
-use sqlx::{Pool, Postgres};
-use tracing::subscriber;
+use chrono::{TimeDelta, Utc};
+use secrecy::SecretString;
+use sqlx::{Postgres, Transaction};
+use std::time::Duration;
 use url::Url;
 use uuid::Uuid;
+use validator::ValidationErrors;
 
+/// Accepted both as an urlencoded form and as JSON.
 #[derive(serde::Deserialize)]
 pub struct SubscribeFormData {
     name: String,
     email: String,
+    /// Honeypot: hidden from humans by the form, so only bots fill it in.
+    #[serde(default)]
+    website: Option<String>,
+    /// From `GET /subscriptions/challenge`, when the proof-of-work challenge is enabled.
+    #[serde(default)]
+    pow_challenge: Option<String>,
+    #[serde(default)]
+    pow_solution: Option<String>,
+    /// e.g. `fr`; takes precedence over `Accept-Language`.
+    #[serde(default)]
+    locale: Option<String>,
+}
+
+impl SubscribeFormData {
+    fn filled_in_honeypot(&self) -> bool {
+        self.website
+            .as_deref()
+            .is_some_and(|w| !w.trim().is_empty())
+    }
+
+    fn verify_proof_of_work(
+        &self,
+        proof_of_work: &ProofOfWork,
+    ) -> Result<SolvedChallenge, ProofOfWorkError> {
+        let (Some(challenge), Some(solution)) = (&self.pow_challenge, &self.pow_solution) else {
+            return Err(ProofOfWorkError::Missing);
+        };
+        proof_of_work.verify(challenge, solution, Utc::now())
+    }
+}
+
+/// Body of a successful subscription, for clients asking for JSON.
+#[derive(serde::Serialize)]
+pub struct SubscribeReply {
+    name: String,
+    email: String,
 }
 
 // Trait used for type conversions which can fail
 impl TryFrom<SubscribeFormData> for NewSubscriber {
-    type Error = SubscriberError;
+    type Error = Vec<SubscriberError>;
 
     fn try_from(form_data: SubscribeFormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form_data.name).map_err(Self::Error::InvalidName)?;
-        let email = SubscriberEmail::parse(form_data.email).map_err(Self::Error::InvalidEmail)?;
-        Ok(NewSubscriber { email, name })
+        parse_new_subscriber(form_data.name, form_data.email)
+    }
+}
+
+/// Validate every field, so that the client can fix all of them in one go.
+pub fn parse_new_subscriber(
+    name: String,
+    email: String,
+) -> Result<NewSubscriber, Vec<SubscriberError>> {
+    let name = SubscriberName::parse(name).map_err(SubscriberError::InvalidName);
+    let email = SubscriberEmail::parse(email).map_err(SubscriberError::InvalidEmail);
+    match (name, email) {
+        (Ok(name), Ok(email)) => Ok(NewSubscriber { email, name }),
+        (name, email) => Err([name.err(), email.err()].into_iter().flatten().collect()),
     }
 }
-#[derive(Debug)]
+
+#[derive(thiserror::Error, Debug)]
 pub enum SubscriberError {
-    InvalidName(String),
-    InvalidEmail(validator::ValidationErrors),
+    #[error("Invalid name: {0}")]
+    InvalidName(SubscriberNameError),
+    #[error("Invalid email: {0}")]
+    InvalidEmail(ValidationErrors),
 }
 
-impl std::fmt::Display for SubscriberError {
+impl SubscriberError {
+    /// Field-level details for the `invalid-params` member of a problem+json response.
+    pub fn invalid_params(&self) -> Vec<InvalidParam> {
+        match self {
+            Self::InvalidName(e) => {
+                let code = match e {
+                    SubscriberNameError::Empty => "empty",
+                    SubscriberNameError::TooLong => "too_long",
+                    SubscriberNameError::ForbiddenCharacter => "forbidden_character",
+                };
+                vec![InvalidParam {
+                    name: "name",
+                    code: code.into(),
+                    reason: e.to_string(),
+                }]
+            }
+            Self::InvalidEmail(e) => e
+                .field_errors()
+                .into_values()
+                .flatten()
+                .map(|error| {
+                    let code = match error.code.as_ref() {
+                        "email" => "invalid_email".to_string(),
+                        other => other.to_string(),
+                    };
+                    let reason = error
+                        .message
+                        .as_ref()
+                        .map(|m| m.to_string())
+                        .unwrap_or_else(|| "The email address is not valid.".into());
+                    InvalidParam {
+                        name: "email",
+                        code,
+                        reason,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeError {
+    #[error("{}", display_all(.0))]
+    ValidationError(Vec<SubscriberError>),
+    #[error(transparent)]
+    ProofOfWorkError(#[from] ProofOfWorkError),
+    #[error("Too many subscription requests for this email address.")]
+    TooManyRequests(Duration),
+    #[error("Failed to store the subscriber in the database.")]
+    StoreError(#[from] sqlx::Error),
+    #[error("Failed to send a confirmation email.")]
+    SendEmailError(#[from] EmailError),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for SubscribeError {
+    fn into_response(self) -> Response {
         match self {
-            Self::InvalidName(msg) => write!(f, "Invalid name: {}", msg),
-            Self::InvalidEmail(e) => write!(f, "Invalid email: {}", e),
+            Self::ValidationError(ref errors) => {
+                tracing::warn!(error = ?self, "Rejected a subscription request");
+                let invalid_params = errors.iter().flat_map(|e| e.invalid_params()).collect();
+                ProblemDetails::validation_error(invalid_params).into_response()
+            }
+            Self::ProofOfWorkError(ref e) => {
+                tracing::warn!(error = ?self, "Rejected a subscription request");
+                let (name, code) = match e {
+                    ProofOfWorkError::Missing => ("pow_solution", "missing"),
+                    ProofOfWorkError::InvalidChallenge => ("pow_challenge", "invalid_challenge"),
+                    ProofOfWorkError::Expired => ("pow_challenge", "expired"),
+                    ProofOfWorkError::AlreadyRedeemed => ("pow_challenge", "already_used"),
+                    ProofOfWorkError::WrongSolution => ("pow_solution", "wrong_solution"),
+                };
+                ProblemDetails::validation_error(vec![InvalidParam {
+                    name,
+                    code: code.into(),
+                    reason: e.to_string(),
+                }])
+                .into_response()
+            }
+            Self::TooManyRequests(retry_after) => {
+                tracing::warn!(error = ?self, "Rejected a subscription request");
+                too_many_requests(retry_after)
+            }
+            Self::StoreError(_) | Self::SendEmailError(_) | Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to process a subscription request");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
         }
     }
 }
 
+fn display_all(errors: &[SubscriberError]) -> String {
+    errors
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 #[axum::debug_handler]
 #[tracing::instrument(
     name = "Adding a new subscriber",
@@ -55,133 +215,222 @@ impl std::fmt::Display for SubscriberError {
 )]
 pub async fn subscribe(
     State(state): State<AppState>,
-    Form(form_data): Form<SubscribeFormData>,
-) -> StatusCode {
-    let db_pool = &state.db;
-    let email_client = &state.email_client;
-
-    let new_subscriber = match form_data.try_into() {
-        // The TryInto trait is automatically implemented for the corresponding type used in TryFrom
-        Ok(subscriber) => subscriber,
-        Err(_) => return StatusCode::BAD_REQUEST,
-    };
+    headers: HeaderMap,
+    FormOrJson(form_data): FormOrJson<SubscribeFormData>,
+) -> Result<Response, SubscribeError> {
+    if form_data.filled_in_honeypot() {
+        // Pretend everything went fine: a bot told it failed would just try harder.
+        tracing::info!("Dropped a subscription request that filled in the honeypot");
+        return Ok(reply(&headers, form_data.name, form_data.email));
+    }
+    let solved_challenge = state
+        .proof_of_work
+        .as_ref()
+        .map(|proof_of_work| form_data.verify_proof_of_work(proof_of_work))
+        .transpose()?;
+    let accepted_languages = accepted_languages(&headers);
+    let locale = state.email_templates.negotiate_locale(
+        form_data
+            .locale
+            .as_deref()
+            .into_iter()
+            .chain(accepted_languages.iter().map(String::as_str)),
+    );
+    // The TryInto trait is automatically implemented for the corresponding type used in TryFrom
+    let new_subscriber: NewSubscriber = form_data
+        .try_into()
+        .map_err(SubscribeError::ValidationError)?;
+    // The same reply whatever the state of the subscription, to avoid leaking who subscribed.
+    let reply = reply(
+        &headers,
+        new_subscriber.name.as_ref().to_owned(),
+        new_subscriber.email.as_ref().to_owned(),
+    );
 
-    let subscriber_id = match insert_subscriber(db_pool, &new_subscriber).await {
-        Ok(subscriber_id) => subscriber_id,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
-    };
-    let subscription_token = generate_subscription_token();
-    if store_token(db_pool, subscriber_id, &subscription_token)
-        .await
-        .is_err()
+    let mut transaction = state.db.begin().await?;
+    // Redeemed along with the subscription: if anything fails, the solution can be used again.
+    if let Some(solved_challenge) = &solved_challenge
+        && !proof_of_work::redeem(&mut transaction, solved_challenge).await?
     {
-        return StatusCode::INTERNAL_SERVER_ERROR;
+        return Err(ProofOfWorkError::AlreadyRedeemed.into());
     }
+    state
+        .subscription_rate_limiter
+        .check_email(&new_subscriber.email)
+        .map_err(SubscribeError::TooManyRequests)?;
+    let Some(subscriber_id) =
+        upsert_pending_subscriber(&mut transaction, &new_subscriber, &locale).await?
+    else {
+        // Already confirmed, or bounced: nothing to do, and no reason to tell the caller.
+        // The challenge is spent all the same, or it could be replayed against this address.
+        transaction.commit().await?;
+        return Ok(reply);
+    };
+    let subscription_token = SubscriptionToken::generate();
+    store_token(
+        &mut transaction,
+        subscriber_id,
+        &subscription_token,
+        &state.hmac_secret,
+    )
+    .await?;
+    transaction.commit().await?;
 
-    if send_confirmation_email(
-        &email_client,
+    send_confirmation_email(
+        &state.email_client,
+        &state.email_templates,
         new_subscriber,
-        state.base_url,
-        &subscription_token,
+        Some(&locale),
+        &state.base_url,
+        subscription_token.as_ref(),
     )
-    .await
-    .is_err()
-    {
-        return StatusCode::INTERNAL_SERVER_ERROR;
+    .await?;
+    Ok(reply)
+}
+
+fn reply(headers: &HeaderMap, name: String, email: String) -> Response {
+    if accepts_json(headers) {
+        Json(SubscribeReply { name, email }).into_response()
+    } else {
+        StatusCode::OK.into_response()
     }
-    StatusCode::OK
 }
 
+/// Make sure there is a subscriber waiting for confirmation for this email address.
+///
+/// Returns the id of the subscriber to send a confirmation to, or `None` if the address
+/// is already confirmed, or was dropped after a bounce or a spam complaint.
+/// Repeat subscriptions reuse the existing row: a pending subscriber stays pending,
+/// and one who had unsubscribed goes back to pending.
+/// Either way, they are written to in the `locale` of their latest request.
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(db_pool, new_subscriber)
+    skip(transaction, new_subscriber)
 )]
-pub async fn insert_subscriber(
-    db_pool: &Pool<Postgres>,
+pub async fn upsert_pending_subscriber(
+    transaction: &mut Transaction<'static, Postgres>,
     new_subscriber: &NewSubscriber,
-) -> Result<Uuid, sqlx::Error> {
-    let subscriber_id = Uuid::new_v4();
-    sqlx::query!(
+    locale: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let inserted = sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, locale)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
         "#,
-        subscriber_id,
+        Uuid::new_v4(),
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        Utc::now(),
+        locale
+    )
+    .fetch_optional(&mut **transaction)
+    .await?;
+    if let Some(row) = inserted {
+        return Ok(Some(row.id));
+    }
+
+    // The address is already known: lock the row so that concurrent requests
+    // for the same email are processed one at a time.
+    let existing = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email = $1 FOR UPDATE"#,
         new_subscriber.email.as_ref(),
+    )
+    .fetch_one(&mut **transaction)
+    .await?;
+    // Any other status is kept: overwriting a bounce or a complaint would lose track of it.
+    if !matches!(
+        existing.status.as_str(),
+        "pending_confirmation" | "unsubscribed"
+    ) {
+        return Ok(None);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, status = 'pending_confirmation', locale = $3
+        WHERE id = $1
+        "#,
+        existing.id,
         new_subscriber.name.as_ref(),
-        Utc::now()
+        locale
     )
-    .execute(db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(subscriber_id)
+    .execute(&mut **transaction)
+    .await?;
+    Ok(Some(existing.id))
 }
 
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, email_templates, new_subscriber)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    email_templates: &EmailTemplates,
     new_subscriber: NewSubscriber,
-    base_url: Url,
+    locale: Option<&str>,
+    base_url: &Url,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    // Dummy email to new subscriber
-    // Ignoring email delivery errors for now
+) -> Result<(), SubscribeError> {
     let path = format!(
         "subscriptions/confirm?subscription_token={}",
         subscription_token
     );
     let confirmation_link = base_url
         .join(&path)
-        .expect("Failed to join confirmation path");
-    let plain_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
+        .context("Failed to build the confirmation link")?;
+    let email =
+        email_templates.confirmation(locale, new_subscriber.name.as_ref(), &confirmation_link);
 
-    email_client
-        .send_email(new_subscriber.email, "Welcome!", &html_body, &plain_body)
+    match email_client
+        .send_email(
+            new_subscriber.email,
+            &email.subject,
+            &email.html,
+            &email.text,
+        )
         .await
+    {
+        // Same reply as for anyone else: whether an address is suppressed is nobody's business.
+        Err(EmailError::Suppressed) => {
+            tracing::info!("Not sending a confirmation email to a suppressed address");
+            Ok(())
+        }
+        outcome => Ok(outcome?),
+    }
 }
 
-/// Generate a random 25-char-long case-sensitive subscription token
-fn generate_subscription_token() -> String {
-    let mut rng = rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
-}
+/// How long a confirmation link can be used for.
+const SUBSCRIPTION_TOKEN_TTL: TimeDelta = TimeDelta::hours(24);
 
 #[tracing::instrument(
     name = "Store subscription token in the database",
-    skip(subscription_token, db_pool)
+    skip(subscription_token, transaction, hmac_secret)
 )]
 pub async fn store_token(
-    db_pool: &Pool<Postgres>,
+    transaction: &mut Transaction<'static, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
+    hmac_secret: &SecretString,
 ) -> Result<(), sqlx::Error> {
+    let created_at = Utc::now();
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
-        subscription_token,
-        subscriber_id
+        r#"
+        INSERT INTO subscription_tokens (
+            subscription_token_hash,
+            subscriber_id,
+            created_at,
+            expires_at
+        )
+        VALUES ($1, $2, $3, $4)
+        "#,
+        subscription_token.hash(hmac_secret),
+        subscriber_id,
+        created_at,
+        created_at + SUBSCRIPTION_TOKEN_TTL
     )
-    .execute(db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
+    .execute(&mut **transaction)
+    .await?;
     Ok(())
 }
@@ -0,0 +1,19 @@
+use crate::state::AppState;
+use axum::Json;
+use axum::extract::State;
+use axum::http::{StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+
+/// Hand out a proof-of-work challenge, to be solved before `POST /subscriptions`.
+///
+/// `404 Not Found` when the challenge is disabled in configuration.
+#[tracing::instrument(name = "Issue a subscription challenge", skip(state))]
+pub async fn subscription_challenge(State(state): State<AppState>) -> Response {
+    let Some(proof_of_work) = &state.proof_of_work else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let challenge = proof_of_work.issue_challenge(Utc::now());
+    // Every challenge can only be used once: it must never be served from a cache
+    ([(header::CACHE_CONTROL, "no-store")], Json(challenge)).into_response()
+}
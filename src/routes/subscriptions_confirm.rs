@@ -1,7 +1,15 @@
+use crate::domain::{SubscriberEmail, SubscriptionToken};
+use crate::email_client::EmailError;
+use crate::routes::unsubscribe_link;
 use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
 use axum::extract::{Query, State};
 use axum::http::StatusCode;
-use sqlx::{Pool, Postgres};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use secrecy::SecretString;
+use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
@@ -9,62 +17,195 @@ pub struct Parameters {
     subscription_token: String,
 }
 
+/// What we know about a subscription token that exists in the database.
+pub enum TokenStatus {
+    Valid { subscriber_id: Uuid },
+    Expired,
+    Consumed,
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmError {
+    #[error("The subscription token is not valid.")]
+    UnknownToken,
+    #[error("The subscription token has expired.")]
+    ExpiredToken,
+    #[error("The subscription token has already been used.")]
+    ConsumedToken,
+    #[error("The subscriber is no longer waiting for a confirmation.")]
+    NotPending,
+    #[error("Failed to update the subscription in the database.")]
+    StoreError(#[from] sqlx::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for ConfirmError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::UnknownToken => {
+                tracing::warn!(error = ?self, "Rejected a confirmation request");
+                StatusCode::UNAUTHORIZED.into_response()
+            }
+            // The link existed, but cannot be used anymore: the subscriber should ask for a new one.
+            Self::ExpiredToken | Self::ConsumedToken | Self::NotPending => {
+                tracing::warn!(error = ?self, "Rejected a confirmation request");
+                StatusCode::GONE.into_response()
+            }
+            Self::StoreError(_) | Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to confirm a subscriber");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
 #[tracing::instrument(name = "Confirm a pending subscriber", skip(state, parameters))]
 pub async fn confirm(
     State(state): State<AppState>,
     Query(parameters): Query<Parameters>,
-) -> StatusCode {
-    let db_pool = &state.db;
-    let id = match get_subscriber_id_from_token(db_pool, &parameters.subscription_token).await {
-        Ok(id) => id,
-        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+) -> Result<StatusCode, ConfirmError> {
+    let mut transaction = state.db.begin().await?;
+    let subscription_token = SubscriptionToken::from(parameters.subscription_token);
+    let token =
+        get_subscriber_id_from_token(&mut transaction, &subscription_token, &state.hmac_secret)
+            .await?;
+    let subscriber_id = match token {
+        None => return Err(ConfirmError::UnknownToken),
+        Some(TokenStatus::Expired) => return Err(ConfirmError::ExpiredToken),
+        Some(TokenStatus::Consumed) => return Err(ConfirmError::ConsumedToken),
+        Some(TokenStatus::Valid { subscriber_id }) => subscriber_id,
     };
-    match id {
-        None => return StatusCode::UNAUTHORIZED,
-        Some(subscriber_id) => {
-            if confirm_subscriber(db_pool, subscriber_id).await.is_err() {
-                return StatusCode::INTERNAL_SERVER_ERROR;
-            }
+    let subscriber = confirm_subscriber(
+        &mut transaction,
+        subscriber_id,
+        &subscription_token,
+        &state.hmac_secret,
+    )
+    .await?
+    .ok_or(ConfirmError::NotPending)?;
+    transaction.commit().await?;
+
+    // The subscription stands whether or not this gets through.
+    if let Err(e) = send_welcome_email(&state, subscriber_id, subscriber).await {
+        tracing::error!(error = ?e, "Failed to send a welcome email");
+    }
+    Ok(StatusCode::OK)
+}
+
+/// Who just confirmed their subscription.
+pub struct ConfirmedSubscriber {
+    email: String,
+    name: String,
+    locale: Option<String>,
+}
+
+#[tracing::instrument(name = "Send a welcome email", skip(state, subscriber))]
+async fn send_welcome_email(
+    state: &AppState,
+    subscriber_id: Uuid,
+    subscriber: ConfirmedSubscriber,
+) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(subscriber.email)
+        .context("The subscriber's stored email address is invalid.")?;
+    let unsubscribe_link = unsubscribe_link(&state.base_url, subscriber_id, &state.hmac_secret)
+        .context("Failed to build the unsubscribe link")?;
+    let email = state.email_templates.welcome(
+        subscriber.locale.as_deref(),
+        &subscriber.name,
+        &unsubscribe_link,
+    );
+    match state
+        .email_client
+        .send_email(recipient, &email.subject, &email.html, &email.text)
+        .await
+    {
+        Err(EmailError::Suppressed) => {
+            tracing::info!("Not sending a welcome email to a suppressed address");
+            Ok(())
         }
+        outcome => Ok(outcome?),
     }
-    StatusCode::OK
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, db_pool))]
+/// Flip the subscriber to `confirmed` and burn the token they used, atomically.
+///
+/// `None` if the subscriber is not pending anymore: an older link which has not expired yet
+/// must not bring back someone who unsubscribed, bounced or complained since.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction, subscription_token, hmac_secret)
+)]
 pub async fn confirm_subscriber(
-    db_pool: &Pool<Postgres>,
+    transaction: &mut Transaction<'static, Postgres>,
     subscriber_id: Uuid,
-) -> Result<(), sqlx::Error> {
+    subscription_token: &SubscriptionToken,
+    hmac_secret: &SecretString,
+) -> Result<Option<ConfirmedSubscriber>, sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE subscription_tokens
+        SET consumed_at = now()
+        WHERE subscription_token_hash = $1
+        "#,
+        subscription_token.hash(hmac_secret)
+    )
+    .execute(&mut **transaction)
+    .await?;
+    sqlx::query_as!(
+        ConfirmedSubscriber,
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed', consented_at = now(), consent_source = 'double_opt_in'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        RETURNING email, name, locale
+        "#,
         subscriber_id
     )
-    .execute(db_pool)
+    .fetch_optional(&mut **transaction)
     .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(())
 }
 
+/// Look a token up, locking its row until the transaction ends:
+/// two concurrent clicks on the same link cannot both see it as unused.
 #[tracing::instrument(
     name = "Get subscriber_id from token",
-    skip(subscription_token, db_pool)
+    skip(subscription_token, transaction, hmac_secret)
 )]
 pub async fn get_subscriber_id_from_token(
-    db_pool: &Pool<Postgres>,
-    subscription_token: &str,
-) -> Result<Option<Uuid>, sqlx::Error> {
+    transaction: &mut Transaction<'static, Postgres>,
+    subscription_token: &SubscriptionToken,
+    hmac_secret: &SecretString,
+) -> Result<Option<TokenStatus>, sqlx::Error> {
     let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
-        subscription_token
+        r#"
+        SELECT subscription_token_hash, subscriber_id, expires_at, consumed_at
+        FROM subscription_tokens
+        WHERE subscription_token_hash = $1 AND NOT is_plaintext
+        FOR UPDATE
+        "#,
+        subscription_token.hash(hmac_secret)
     )
-    .fetch_optional(db_pool)
-    .await
-    .map_err(|e| {
-        tracing::error!("Failed to execute query: {:?}", e);
-        e
-    })?;
-    Ok(result.map(|r| r.subscriber_id))
+    .fetch_optional(&mut **transaction)
+    .await?;
+    // The index lookup only ever sees keyed hashes; the final check is done in constant time too.
+    let result =
+        result.filter(|r| subscription_token.matches(&r.subscription_token_hash, hmac_secret));
+    Ok(result.map(|r| {
+        if r.consumed_at.is_some() {
+            TokenStatus::Consumed
+        } else if r.expires_at <= Utc::now() {
+            TokenStatus::Expired
+        } else {
+            TokenStatus::Valid {
+                subscriber_id: r.subscriber_id,
+            }
+        }
+    }))
 }
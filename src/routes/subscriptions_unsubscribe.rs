@@ -0,0 +1,160 @@
+use crate::domain::UnsubscribeToken;
+use crate::state::AppState;
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use secrecy::SecretString;
+use sqlx::PgPool;
+use url::Url;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("The unsubscribe token is not valid.")]
+    InvalidToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for UnsubscribeError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::InvalidToken => {
+                tracing::warn!(error = ?self, "Rejected an unsubscribe request");
+                StatusCode::UNAUTHORIZED.into_response()
+            }
+            Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to unsubscribe");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Build the link a subscriber follows (or their mail client POSTs to) to unsubscribe.
+pub fn unsubscribe_link(
+    base_url: &Url,
+    subscriber_id: Uuid,
+    hmac_secret: &SecretString,
+) -> Result<Url, url::ParseError> {
+    let token = UnsubscribeToken::generate(subscriber_id, hmac_secret);
+    unsubscribe_url(base_url, token.as_ref())
+}
+
+/// Relative to `base_url`, which may have a path of its own (e.g. `/newsletter/`).
+fn unsubscribe_url(base_url: &Url, token: &str) -> Result<Url, url::ParseError> {
+    let mut link = base_url.join("subscriptions/unsubscribe")?;
+    link.query_pairs_mut().append_pair("token", token);
+    Ok(link)
+}
+
+/// Ask for a confirmation rather than unsubscribing straight away:
+/// link scanners and previews issue `GET`s on every link in an email.
+#[tracing::instrument(name = "Show unsubscribe page", skip(state, parameters))]
+pub async fn unsubscribe_form(
+    State(state): State<AppState>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<Html<String>, UnsubscribeError> {
+    UnsubscribeToken::verify(&parameters.token, &state.hmac_secret)
+        .ok_or(UnsubscribeError::InvalidToken)?;
+    let action = unsubscribe_url(&state.base_url, &parameters.token)
+        .context("Failed to build the unsubscribe link")?;
+    let action = htmlescape::encode_attribute(action.as_str());
+    Ok(Html(format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribe</title>
+</head>
+<body>
+    <p>Do you want to stop receiving our newsletter?</p>
+    <form action="{action}" method="post">
+        <button type="submit">Unsubscribe</button>
+    </form>
+</body>
+</html>"#,
+    )))
+}
+
+/// Handles both the confirmation form and RFC 8058 one-click requests
+/// (a `POST` with a `List-Unsubscribe=One-Click` body, which carries nothing we need).
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(state, parameters),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe(
+    State(state): State<AppState>,
+    Query(parameters): Query<UnsubscribeParameters>,
+) -> Result<Html<&'static str>, UnsubscribeError> {
+    let subscriber_id = UnsubscribeToken::verify(&parameters.token, &state.hmac_secret)
+        .ok_or(UnsubscribeError::InvalidToken)?;
+    tracing::Span::current().record("subscriber_id", tracing::field::display(subscriber_id));
+    mark_subscriber_as_unsubscribed(&state.db, subscriber_id)
+        .await
+        .context("Failed to update the subscriber status")?;
+    Ok(Html(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Unsubscribed</title>
+</head>
+<body>
+    <p>You have been unsubscribed. You will not receive any more issues.</p>
+</body>
+</html>"#,
+    ))
+}
+
+#[tracing::instrument(skip(db_pool))]
+async fn mark_subscriber_as_unsubscribed(
+    db_pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    // Unsubscribing twice is not an error: the second click finds nothing left to do.
+    // Bounces and complaints are left alone, we would lose track of them otherwise.
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'unsubscribed'
+        WHERE id = $1 AND status IN ('confirmed', 'pending_confirmation')
+        "#,
+        subscriber_id
+    )
+    .execute(db_pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::unsubscribe_link;
+    use secrecy::SecretString;
+    use url::Url;
+    use uuid::Uuid;
+
+    #[test]
+    fn unsubscribe_links_keep_the_path_of_the_base_url() {
+        let base_url = Url::parse("https://example.com/newsletter/").unwrap();
+        let hmac_secret = SecretString::from("secret");
+
+        let link = unsubscribe_link(&base_url, Uuid::new_v4(), &hmac_secret).unwrap();
+
+        assert_eq!("/newsletter/subscriptions/unsubscribe", link.path());
+        assert!(link.query_pairs().any(|(key, _)| key == "token"));
+    }
+}
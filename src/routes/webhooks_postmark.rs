@@ -0,0 +1,223 @@
+use crate::authentication::basic_authentication;
+use crate::configuration::PostmarkWebhookSettings;
+use crate::state::AppState;
+use crate::suppressions::{SuppressionReason, add_suppression};
+use crate::utils::error_chain_fmt;
+use anyhow::Context;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::{Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+/// The events we act upon; Postmark sends a payload per event, tagged by `RecordType`.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+enum PostmarkEvent {
+    Bounce(BounceEvent),
+    SpamComplaint(BounceEvent),
+    /// Deliveries, opens, clicks...: acknowledged and ignored.
+    #[serde(other)]
+    Other,
+}
+
+/// Spam complaints are reported through the bounce API too, with the same fields.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BounceEvent {
+    #[serde(rename = "ID")]
+    id: serde_json::Value,
+    /// e.g. `HardBounce`, `SoftBounce` or `SpamComplaint`
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    email: String,
+    /// Whether Postmark stopped sending to the address.
+    #[serde(default)]
+    inactive: bool,
+    bounced_at: Option<DateTime<Utc>>,
+}
+
+#[derive(thiserror::Error)]
+pub enum PostmarkWebhookError {
+    #[error("Authentication failed.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The webhook payload could not be parsed.")]
+    InvalidPayload(#[source] serde_json::Error),
+    #[error("Failed to record the email event in the database.")]
+    StoreError(#[from] sqlx::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for PostmarkWebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl IntoResponse for PostmarkWebhookError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::AuthError(_) => {
+                tracing::warn!(error = ?self, "Rejected an unauthenticated webhook call");
+                let mut response = StatusCode::UNAUTHORIZED.into_response();
+                let header_value = HeaderValue::from_static(r#"Basic realm="webhooks""#);
+                response
+                    .headers_mut()
+                    .insert(header::WWW_AUTHENTICATE, header_value);
+                response
+            }
+            Self::InvalidPayload(_) => {
+                tracing::warn!(error = ?self, "Rejected a webhook call");
+                StatusCode::BAD_REQUEST.into_response()
+            }
+            // Postmark retries anything but a 2xx: the event is not lost.
+            Self::StoreError(_) | Self::UnexpectedError(_) => {
+                tracing::error!(error = ?self, "Failed to process a webhook call");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// Ingest bounce and spam complaint notifications from Postmark.
+///
+/// Hard bounces mark the subscriber as `bounced`, complaints as `complained`:
+/// only `confirmed` subscribers are sent issues, so both stop receiving them.
+/// Either way the address is added to the suppression list, so it is never mailed again.
+#[tracing::instrument(name = "Receive a Postmark webhook", skip(state, headers, body))]
+pub async fn postmark_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, PostmarkWebhookError> {
+    authenticate(&headers, &state.postmark_webhook).map_err(PostmarkWebhookError::AuthError)?;
+    let payload: serde_json::Value =
+        serde_json::from_slice(&body).map_err(PostmarkWebhookError::InvalidPayload)?;
+    let event = serde_json::from_value::<PostmarkEvent>(payload.clone())
+        .map_err(PostmarkWebhookError::InvalidPayload)?;
+    let (record_type, event, new_status) = match event {
+        PostmarkEvent::Bounce(event) => {
+            // Soft bounces and the like: the next send may well succeed
+            let is_permanent = event.inactive || event.bounce_type.as_deref() == Some("HardBounce");
+            (
+                "Bounce",
+                event,
+                is_permanent.then_some(("bounced", SuppressionReason::HardBounce)),
+            )
+        }
+        PostmarkEvent::SpamComplaint(event) => (
+            "SpamComplaint",
+            event,
+            Some(("complained", SuppressionReason::SpamComplaint)),
+        ),
+        PostmarkEvent::Other => return Ok(StatusCode::OK),
+    };
+
+    let mut transaction = state.db.begin().await?;
+    let subscriber_id = sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions WHERE lower(email) = lower($1) FOR UPDATE"#,
+        event.email
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let is_new = record_event(
+        &mut transaction,
+        record_type,
+        &event,
+        subscriber_id,
+        payload,
+    )
+    .await?;
+    // A retried delivery of an event we already processed
+    if !is_new {
+        return Ok(StatusCode::OK);
+    }
+    // Suppressed even if they are not (or no longer) subscribed: no email of ours should reach them.
+    if let Some((_, reason)) = new_status {
+        add_suppression(&mut *transaction, &event.email, reason, "postmark").await?;
+    }
+    if let (Some(subscriber_id), Some((new_status, _))) = (subscriber_id, new_status) {
+        sqlx::query!(
+            r#"
+            UPDATE subscriptions
+            SET status = $2
+            -- A complaint is the stronger signal: a later bounce does not overwrite it
+            WHERE id = $1 AND status <> 'complained'
+            "#,
+            subscriber_id,
+            new_status
+        )
+        .execute(&mut *transaction)
+        .await?;
+        tracing::info!(%subscriber_id, new_status, "Stopped mailing a subscriber");
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the email event.")?;
+    Ok(StatusCode::OK)
+}
+
+fn authenticate(
+    headers: &HeaderMap,
+    settings: &PostmarkWebhookSettings,
+) -> Result<(), anyhow::Error> {
+    let credentials = basic_authentication(headers)?;
+    // Compare both in constant time, and both every time
+    let username_matches = credentials
+        .username
+        .as_bytes()
+        .ct_eq(settings.username.as_bytes());
+    let password_matches = credentials
+        .password
+        .expose_secret()
+        .as_bytes()
+        .ct_eq(settings.password.expose_secret().as_bytes());
+    if bool::from(username_matches & password_matches) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!("Invalid webhook credentials."))
+    }
+}
+
+/// Returns `false` if the event had already been recorded.
+#[tracing::instrument(skip(transaction, event, payload))]
+async fn record_event(
+    transaction: &mut Transaction<'static, Postgres>,
+    record_type: &str,
+    event: &BounceEvent,
+    subscriber_id: Option<Uuid>,
+    payload: serde_json::Value,
+) -> Result<bool, sqlx::Error> {
+    // Numeric in practice, but we only need it to tell events apart
+    let provider_event_id = match &event.id {
+        serde_json::Value::String(id) => id.clone(),
+        id => id.to_string(),
+    };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            id, provider, provider_event_id, record_type, bounce_type,
+            email, subscriber_id, occurred_at, received_at, payload
+        )
+        VALUES ($1, 'postmark', $2, $3, $4, $5, $6, $7, now(), $8)
+        ON CONFLICT (provider, provider_event_id) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        provider_event_id,
+        record_type,
+        event.bounce_type,
+        event.email,
+        subscriber_id,
+        event.bounced_at,
+        payload
+    )
+    .execute(&mut **transaction)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
@@ -0,0 +1,52 @@
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use tower_sessions::Session;
+use tower_sessions::session::Error;
+use uuid::Uuid;
+
+/// Strongly-typed wrapper around `tower_sessions::Session`, so that handlers
+/// cannot mistype keys or store values of the wrong type.
+pub struct TypedSession(Session);
+
+impl TypedSession {
+    const USER_ID_KEY: &'static str = "user_id";
+    const FLASH_KEY: &'static str = "flash";
+
+    /// Issue a new session id, to prevent session fixation on privilege changes.
+    pub async fn renew(&self) -> Result<(), Error> {
+        self.0.cycle_id().await
+    }
+
+    pub async fn insert_user_id(&self, user_id: Uuid) -> Result<(), Error> {
+        self.0.insert(Self::USER_ID_KEY, user_id).await
+    }
+
+    pub async fn get_user_id(&self) -> Result<Option<Uuid>, Error> {
+        self.0.get(Self::USER_ID_KEY).await
+    }
+
+    /// Store a one-off message to be displayed on the next rendered page.
+    pub async fn insert_flash(&self, message: &str) -> Result<(), Error> {
+        self.0.insert(Self::FLASH_KEY, message).await
+    }
+
+    /// Retrieve the pending flash message, if any, removing it from the session.
+    pub async fn take_flash(&self) -> Result<Option<String>, Error> {
+        self.0.remove(Self::FLASH_KEY).await
+    }
+
+    pub async fn log_out(&self) -> Result<(), Error> {
+        self.0.flush().await
+    }
+}
+
+impl<S> FromRequestParts<S> for TypedSession
+where
+    S: Send + Sync,
+{
+    type Rejection = <Session as FromRequestParts<S>>::Rejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Session::from_request_parts(parts, state).await.map(Self)
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{self, ExpiredDeletion, SessionStore};
+
+/// `tower-sessions` backend keeping session records in the `sessions` table,
+/// so that login state survives restarts and is shared across replicas.
+#[derive(Clone, Debug)]
+pub struct PostgresSessionStore {
+    db_pool: PgPool,
+}
+
+impl PostgresSessionStore {
+    pub fn new(db_pool: PgPool) -> Self {
+        Self { db_pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for PostgresSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        // Session ids are random, but retry on the off chance that we hit an existing one.
+        loop {
+            let data = serde_json::to_value(&record.data)
+                .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+            let inserted = sqlx::query!(
+                r#"
+                INSERT INTO sessions (id, data, expiry_date)
+                VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+                record.id.to_string(),
+                data,
+                to_chrono(record.expiry_date)?,
+            )
+            .execute(&self.db_pool)
+            .await
+            .context("Failed to insert a new session")
+            .map_err(backend_error)?
+            .rows_affected();
+            if inserted == 1 {
+                return Ok(());
+            }
+            record.id = Id::default();
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let data = serde_json::to_value(&record.data)
+            .map_err(|e| session_store::Error::Encode(e.to_string()))?;
+        sqlx::query!(
+            r#"
+            INSERT INTO sessions (id, data, expiry_date)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO UPDATE
+            SET data = EXCLUDED.data, expiry_date = EXCLUDED.expiry_date
+            "#,
+            record.id.to_string(),
+            data,
+            to_chrono(record.expiry_date)?,
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to save session")
+        .map_err(backend_error)?;
+        Ok(())
+    }
+
+    async fn load(&self, session_id: &Id) -> session_store::Result<Option<Record>> {
+        let row = sqlx::query!(
+            r#"SELECT data, expiry_date FROM sessions WHERE id = $1 AND expiry_date > now()"#,
+            session_id.to_string(),
+        )
+        .fetch_optional(&self.db_pool)
+        .await
+        .context("Failed to load session")
+        .map_err(backend_error)?;
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let data = serde_json::from_value(row.data)
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        let expiry_date = time::OffsetDateTime::from_unix_timestamp(row.expiry_date.timestamp())
+            .map_err(|e| session_store::Error::Decode(e.to_string()))?;
+        Ok(Some(Record {
+            id: *session_id,
+            data,
+            expiry_date,
+        }))
+    }
+
+    async fn delete(&self, session_id: &Id) -> session_store::Result<()> {
+        sqlx::query!(
+            r#"DELETE FROM sessions WHERE id = $1"#,
+            session_id.to_string()
+        )
+        .execute(&self.db_pool)
+        .await
+        .context("Failed to delete session")
+        .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+/// Expired sessions are never loaded, but only this deletes them: run it periodically.
+#[async_trait::async_trait]
+impl ExpiredDeletion for PostgresSessionStore {
+    async fn delete_expired(&self) -> session_store::Result<()> {
+        sqlx::query!(r#"DELETE FROM sessions WHERE expiry_date < now()"#)
+            .execute(&self.db_pool)
+            .await
+            .context("Failed to delete expired sessions")
+            .map_err(backend_error)?;
+        Ok(())
+    }
+}
+
+fn to_chrono(expiry_date: time::OffsetDateTime) -> session_store::Result<DateTime<Utc>> {
+    DateTime::from_timestamp(expiry_date.unix_timestamp(), 0)
+        .ok_or_else(|| session_store::Error::Encode("Session expiry is out of range".into()))
+}
+
+fn backend_error(e: anyhow::Error) -> session_store::Error {
+    session_store::Error::Backend(format!("{:?}", e))
+}
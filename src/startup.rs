@@ -1,19 +1,39 @@
-use crate::configuration::{DatabaseSettings, Settings};
-use crate::email_client::EmailClient;
-use crate::routes::{confirm, health_check, subscribe};
+use crate::authentication::{compute_password_hash, reject_anonymous_users};
+use crate::configuration::{DatabaseSettings, InitialAdminSettings, Settings};
+use crate::domain::SubscriptionToken;
+use crate::rate_limit::{SubscriptionRateLimiter, rate_limit_subscriptions};
+use crate::routes::{
+    MAX_IMPORT_SIZE, add_suppression, admin_dashboard, cancel_scheduled_issue, confirm,
+    create_issue, export_subscribers, get_issue, health_check, import_subscribers, list_issues,
+    list_subscribers, list_suppressions, log_out, login, login_form, postmark_webhook,
+    preview_issue, publish_issue, publish_newsletter, remove_suppression, schedule_issue,
+    send_test_issue, subscribe, subscription_challenge, unsubscribe, unsubscribe_form,
+    update_issue,
+};
+use crate::session_store::PostgresSessionStore;
 use crate::state::AppState;
+use crate::suppressions::PostgresSuppressionList;
+use crate::telemetry::spawn_blocking_with_tracing;
+use anyhow::Context;
 use axum::{
     Router,
-    routing::{get, post},
+    extract::DefaultBodyLimit,
+    middleware,
+    routing::{delete, get, post, put},
 };
 use http::Request;
-use sqlx::{Pool, Postgres, postgres::PgPoolOptions};
+use secrecy::{ExposeSecret, SecretString};
+use sqlx::{PgPool, Pool, Postgres, postgres::PgPoolOptions};
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::net::TcpListener;
 use tower_http::{
     request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
     trace::TraceLayer,
 };
+use tower_sessions::cookie::SameSite;
+use tower_sessions::{Expiry, SessionManagerLayer};
 
 pub struct Application {
     listener: TcpListener,
@@ -22,26 +42,46 @@ pub struct Application {
 }
 
 impl Application {
-    pub async fn build(config: Settings) -> Result<Self, std::io::Error> {
+    pub async fn build(config: Settings) -> Result<Self, anyhow::Error> {
         let db = get_connection_pool(&config.database);
+        hash_plaintext_subscription_tokens(&db, &config.application.hmac_secret)
+            .await
+            .context("Failed to hash plaintext subscription tokens")?;
+        if let Some(initial_admin) = config.initial_admin.clone() {
+            create_initial_admin(&db, initial_admin)
+                .await
+                .context("Failed to create the initial admin")?;
+        }
 
-        let sender_email = config
+        let email_client = config
             .email_client
-            .sender()
-            .expect("Invalid sender email address.");
-        let timeout = config.email_client.timeout();
-        let email_client = EmailClient::new(
-            config.email_client.base_url,
-            sender_email,
-            config.email_client.auth_token,
-            timeout,
+            .client(Arc::new(PostgresSuppressionList::new(db.clone())));
+        let email_templates = Arc::new(
+            config
+                .email_templates
+                .templates()
+                .context("Failed to load the email templates")?,
         );
         let base_url = config.application.base_url.clone();
+        let hmac_secret = config.application.hmac_secret.clone();
+        let subscription_rate_limiter = SubscriptionRateLimiter::new(&config.rate_limit);
+        let proof_of_work = config.proof_of_work.proof_of_work(hmac_secret.clone());
+        let test_recipients = config
+            .newsletter_issues
+            .test_recipients()
+            .context("Invalid `newsletter_issues.test_recipients` setting")?
+            .into();
 
         let state = AppState {
             db,
             email_client,
+            email_templates,
             base_url,
+            hmac_secret,
+            subscription_rate_limiter,
+            proof_of_work,
+            postmark_webhook: config.postmark_webhook.clone(),
+            test_recipients,
         };
 
         let addr = format!("{}:{}", config.application.host, config.application.port);
@@ -62,11 +102,61 @@ impl Application {
     }
 
     pub fn define_router(state: AppState) -> Router {
+        // Login sessions live in Postgres: no extra service to run and they are shared across replicas.
+        let session_layer = SessionManagerLayer::new(PostgresSessionStore::new(state.db.clone()))
+            .with_name("session_id")
+            .with_http_only(true)
+            .with_secure(true)
+            .with_same_site(SameSite::Lax)
+            .with_expiry(Expiry::OnInactivity(time::Duration::hours(1)));
+
+        // Every route in here requires a logged-in admin
+        let admin_routes = Router::new()
+            .route("/dashboard", get(admin_dashboard))
+            .route("/logout", post(log_out))
+            .route("/issues", get(list_issues).post(create_issue))
+            .route("/issues/{issue_id}", get(get_issue).put(update_issue))
+            .route("/issues/{issue_id}/preview", get(preview_issue))
+            .route("/issues/{issue_id}/test", post(send_test_issue))
+            .route("/issues/{issue_id}/publish", post(publish_issue))
+            .route(
+                "/issues/{issue_id}/schedule",
+                put(schedule_issue).delete(cancel_scheduled_issue),
+            )
+            .route("/subscribers", get(list_subscribers))
+            .route("/subscribers/export.csv", get(export_subscribers))
+            .route(
+                "/subscribers/import",
+                post(import_subscribers).layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+            )
+            .route(
+                "/suppressions",
+                get(list_suppressions).post(add_suppression),
+            )
+            .route("/suppressions/{email}", delete(remove_suppression))
+            .route_layer(middleware::from_fn(reject_anonymous_users));
+
         Router::new()
             .route("/health_check", get(health_check))
-            .route("/subscriptions", post(subscribe))
+            .route(
+                "/subscriptions",
+                post(subscribe).layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    rate_limit_subscriptions,
+                )),
+            )
+            .route("/subscriptions/challenge", get(subscription_challenge))
             .route("/subscriptions/confirm", get(confirm))
+            .route(
+                "/subscriptions/unsubscribe",
+                get(unsubscribe_form).post(unsubscribe),
+            )
+            .route("/newsletters", post(publish_newsletter))
+            .route("/webhooks/postmark", post(postmark_webhook))
+            .route("/login", get(login_form).post(login))
+            .nest("/admin", admin_routes)
             .with_state(state)
+            .layer(session_layer)
             .layer(
                 TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
                     let request_id = request
@@ -94,8 +184,91 @@ impl Application {
             port: _,
         } = self;
         let router = Self::define_router(state);
-        axum::serve(listener, router.into_make_service()).await
+        // Rate limiting needs the address of the peer
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    }
+}
+
+/// Subscription tokens created before we started hashing them are still stored in plaintext.
+/// Hashing requires the application secret, which the SQL migrations do not have: it happens here.
+#[tracing::instrument(skip_all)]
+pub async fn hash_plaintext_subscription_tokens(
+    db_pool: &PgPool,
+    hmac_secret: &SecretString,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = db_pool.begin().await?;
+    let plaintext_tokens = sqlx::query!(
+        r#"
+        SELECT subscription_token_hash AS subscription_token
+        FROM subscription_tokens
+        WHERE is_plaintext
+        FOR UPDATE
+        "#
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    for row in &plaintext_tokens {
+        let token = SubscriptionToken::from(row.subscription_token.clone());
+        sqlx::query!(
+            r#"
+            UPDATE subscription_tokens
+            SET subscription_token_hash = $2, is_plaintext = false
+            WHERE subscription_token_hash = $1
+            "#,
+            token.as_ref(),
+            token.hash(hmac_secret)
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+    transaction.commit().await?;
+    if !plaintext_tokens.is_empty() {
+        tracing::info!(
+            n_tokens = plaintext_tokens.len(),
+            "Hashed plaintext subscription tokens"
+        );
+    }
+    Ok(())
+}
+
+/// Credentials have no place in migrations: the first admin comes from the configuration instead.
+/// Nothing happens once there is any user, so the configured password can be changed for good.
+#[tracing::instrument(skip_all, fields(username = %initial_admin.username))]
+pub async fn create_initial_admin(
+    db_pool: &PgPool,
+    initial_admin: InitialAdminSettings,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = db_pool.begin().await?;
+    // Replicas starting together race for it: the table lock lets exactly one of them in
+    sqlx::query!("LOCK TABLE users IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await?;
+    let has_users = sqlx::query!(r#"SELECT EXISTS (SELECT 1 FROM users) AS "exists!""#)
+        .fetch_one(&mut *transaction)
+        .await?
+        .exists;
+    if has_users {
+        return Ok(());
     }
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(initial_admin.password))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)"#,
+        uuid::Uuid::new_v4(),
+        initial_admin.username,
+        password_hash.expose_secret(),
+    )
+    .execute(&mut *transaction)
+    .await?;
+    transaction.commit().await?;
+    tracing::info!("Created the initial admin");
+    Ok(())
 }
 
 pub fn get_connection_pool(db_config: &DatabaseSettings) -> Pool<Postgres> {
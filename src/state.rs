@@ -1,12 +1,27 @@
 // use crate::configuration::Settings;
+use crate::configuration::PostmarkWebhookSettings;
+use crate::domain::SubscriberEmail;
 use crate::email_client::EmailClient;
+use crate::proof_of_work::ProofOfWork;
+use crate::rate_limit::SubscriptionRateLimiter;
+use crate::templates::EmailTemplates;
+use secrecy::SecretString;
 use sqlx::PgPool;
+use std::sync::Arc;
 use url::Url;
 
 #[derive(Clone)] // Important for state sharing
 pub struct AppState {
     pub db: PgPool,
     pub email_client: EmailClient,
+    pub email_templates: Arc<EmailTemplates>,
     // pub config: Settings, // TODO: Seeing if I really need config in State. I don't think I do.
     pub base_url: Url,
+    pub hmac_secret: SecretString,
+    pub subscription_rate_limiter: SubscriptionRateLimiter,
+    /// `None` unless the subscription proof-of-work challenge is enabled.
+    pub proof_of_work: Option<ProofOfWork>,
+    pub postmark_webhook: PostmarkWebhookSettings,
+    /// The only addresses draft issues can be test-sent to.
+    pub test_recipients: Arc<[SubscriberEmail]>,
 }
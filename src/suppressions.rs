@@ -0,0 +1,94 @@
+use crate::domain::SubscriberEmail;
+use crate::email_client::SuppressionList;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+
+/// Why an address must never be mailed again.
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SuppressionReason {
+    HardBounce,
+    SpamComplaint,
+    /// The person asked us to forget about them.
+    ErasureRequest,
+    Manual,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::HardBounce => "hard_bounce",
+            Self::SpamComplaint => "spam_complaint",
+            Self::ErasureRequest => "erasure_request",
+            Self::Manual => "manual",
+        }
+    }
+}
+
+#[derive(serde::Serialize, Debug)]
+pub struct Suppression {
+    pub email: String,
+    pub reason: String,
+    /// Who added it, e.g. `postmark` or `admin`.
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// The `suppressions` table, consulted by [`EmailClient`](crate::email_client::EmailClient)
+/// before every send.
+pub struct PostgresSuppressionList {
+    db: PgPool,
+}
+
+impl PostgresSuppressionList {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait::async_trait]
+impl SuppressionList for PostgresSuppressionList {
+    async fn is_suppressed(&self, email: &SubscriberEmail) -> Result<bool, anyhow::Error> {
+        is_suppressed(&self.db, email.as_ref())
+            .await
+            .context("Failed to check the suppression list.")
+    }
+}
+
+/// Addresses are compared case-insensitively: they are stored lowercased.
+pub async fn is_suppressed(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT EXISTS (SELECT 1 FROM suppressions WHERE email = lower($1)) AS "exists!""#,
+        email
+    )
+    .fetch_one(executor)
+    .await
+}
+
+/// Returns `None` if the address was already suppressed: the first reason is kept.
+#[tracing::instrument(name = "Suppress an email address", skip(executor, email))]
+pub async fn add_suppression(
+    executor: impl PgExecutor<'_>,
+    email: &str,
+    reason: SuppressionReason,
+    source: &str,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        INSERT INTO suppressions (email, reason, source, created_at)
+        VALUES (lower($1), $2, $3, now())
+        ON CONFLICT (email) DO NOTHING
+        RETURNING email, reason, source, created_at
+        "#,
+        email,
+        reason.as_str(),
+        source
+    )
+    .fetch_optional(executor)
+    .await
+}
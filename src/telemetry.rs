@@ -1,3 +1,4 @@
+use tokio::task::JoinHandle;
 use tracing::Subscriber;
 use tracing::subscriber::set_global_default;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -37,3 +38,13 @@ pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
     // set_global_default specifies the subscriber used to process spans
     set_global_default(subscriber).expect("Failed to set subscriber");
 }
+
+/// Run a CPU-heavy closure on tokio's blocking thread pool, keeping it attached to the current span.
+pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    let current_span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || current_span.in_scope(f))
+}
@@ -0,0 +1,49 @@
+use super::Definition;
+use super::template::{Escaping, Template};
+use anyhow::Context;
+use std::collections::HashMap;
+
+/// The messages of one locale: email name, then message key.
+///
+/// A catalog does not have to cover every message: missing ones are taken
+/// from the catalog of the default locale.
+#[derive(Debug)]
+pub(super) struct Catalog {
+    messages: HashMap<String, HashMap<String, Template>>,
+}
+
+impl Catalog {
+    /// Parse a YAML catalog, with a section per email.
+    ///
+    /// Unknown emails or messages are rejected, as are messages using variables
+    /// their email does not provide: both are typos waiting to ship.
+    pub(super) fn parse(source: &str, definitions: &[&Definition]) -> Result<Self, anyhow::Error> {
+        let sections = config::Config::builder()
+            .add_source(config::File::from_str(source, config::FileFormat::Yaml))
+            .build()
+            .and_then(|c| c.try_deserialize::<HashMap<String, HashMap<String, String>>>())
+            .context("The catalog is not a map of emails to messages.")?;
+        let mut messages = HashMap::new();
+        for (email, section) in sections {
+            let definition = definitions
+                .iter()
+                .find(|d| d.name == email)
+                .with_context(|| format!("Unknown email `{}`.", email))?;
+            let mut compiled = HashMap::new();
+            for (key, message) in section {
+                if !definition.messages.contains(&key.as_str()) {
+                    anyhow::bail!("Unknown message `{}.{}`.", email, key);
+                }
+                let template = Template::compile(&message, Escaping::None, definition.variables)
+                    .with_context(|| format!("Invalid message `{}.{}`.", email, key))?;
+                compiled.insert(key, template);
+            }
+            messages.insert(email, compiled);
+        }
+        Ok(Self { messages })
+    }
+
+    pub(super) fn message(&self, email: &str, key: &str) -> Option<&Template> {
+        self.messages.get(email)?.get(key)
+    }
+}
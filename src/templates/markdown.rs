@@ -0,0 +1,428 @@
+//! Newsletter issues written in Markdown, rendered to both HTML and plain text.
+//!
+//! Parsing is CommonMark, plus tables and `~~strikethrough~~`, courtesy of `pulldown-cmark`.
+//! The HTML is safe to send as is: raw HTML in the source is escaped like any other text,
+//! links and images are only kept for `http`, `https` (and `mailto` for links) URLs,
+//! and what comes out is sanitized by `ammonia` on top of that.
+
+use pulldown_cmark::{Event, HeadingLevel, LinkType, Options, Parser, Tag, TagEnd};
+use std::collections::HashSet;
+
+/// The HTML and plain-text versions of a Markdown document.
+#[derive(Debug)]
+pub struct RenderedMarkdown {
+    pub html: String,
+    /// Links become footnotes, listed at the end: `[1] https://...`.
+    pub text: String,
+}
+
+pub fn render_markdown(source: &str) -> RenderedMarkdown {
+    let events = parse(source);
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, events.iter().cloned());
+    let html = ammonia::Builder::default()
+        .url_schemes(HashSet::from(LINK_SCHEMES))
+        .url_relative(ammonia::UrlRelative::Deny)
+        .link_rel(None)
+        .clean(&html)
+        .to_string();
+    RenderedMarkdown {
+        html,
+        text: render_text(events),
+    }
+}
+
+const LINK_SCHEMES: [&str; 3] = ["http", "https", "mailto"];
+const IMAGE_SCHEMES: [&str; 2] = ["http", "https"];
+
+/// The events of `source`, minus the links and images we are not willing to send:
+/// those keep their text only.
+fn parse(source: &str) -> Vec<Event<'_>> {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+    // Whether each link or image we are in was kept, to know what to do with its end
+    let mut kept = Vec::new();
+    let mut events = Vec::new();
+    for event in Parser::new_ext(source, options) {
+        let keep = match &event {
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                ..
+            }) => {
+                let keep = *link_type == LinkType::Email || is_safe_url(dest_url, &LINK_SCHEMES);
+                kept.push(keep);
+                keep
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                let keep = is_safe_url(dest_url, &IMAGE_SCHEMES);
+                kept.push(keep);
+                keep
+            }
+            Event::End(TagEnd::Link | TagEnd::Image) => kept.pop().unwrap_or(true),
+            _ => true,
+        };
+        if keep {
+            events.push(match event {
+                Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+                event => event,
+            });
+        }
+    }
+    events
+}
+
+/// `javascript:` and the like never make it into an email, nor do relative URLs.
+fn is_safe_url(url: &str, schemes: &[&str]) -> bool {
+    url::Url::parse(url).is_ok_and(|parsed| schemes.contains(&parsed.scheme()))
+}
+
+/// Something being rendered to text: the blocks it is made of, then its inline text so far.
+struct Frame {
+    kind: FrameKind,
+    blocks: Vec<String>,
+    text: String,
+}
+
+enum FrameKind {
+    Document,
+    /// A paragraph, a block of HTML or a table cell: inline text only.
+    Text,
+    Heading(HeadingLevel),
+    Code,
+    Quote,
+    List(Option<u64>),
+    Item,
+    Table,
+    Row,
+    Link(String),
+    Image(String),
+}
+
+impl Frame {
+    fn new(kind: FrameKind) -> Self {
+        Self {
+            kind,
+            blocks: Vec::new(),
+            text: String::new(),
+        }
+    }
+
+    fn push_block(&mut self, block: String) {
+        self.flush_text();
+        self.blocks.push(block);
+    }
+
+    /// Text which is not part of a block of its own, e.g. the first line of a list item.
+    fn flush_text(&mut self) {
+        let text = std::mem::take(&mut self.text);
+        let text = text.trim_end_matches('\n');
+        if !text.is_empty() {
+            self.blocks.push(text.to_string());
+        }
+    }
+}
+
+fn frame_kind(tag: Tag<'_>) -> Option<FrameKind> {
+    let kind = match tag {
+        Tag::Paragraph | Tag::HtmlBlock | Tag::TableCell => FrameKind::Text,
+        Tag::Heading { level, .. } => FrameKind::Heading(level),
+        Tag::CodeBlock(_) => FrameKind::Code,
+        Tag::BlockQuote(_) => FrameKind::Quote,
+        Tag::List(start) => FrameKind::List(start),
+        Tag::Item => FrameKind::Item,
+        Tag::Table(_) => FrameKind::Table,
+        Tag::TableHead | Tag::TableRow => FrameKind::Row,
+        Tag::Link {
+            link_type: LinkType::Email,
+            dest_url,
+            ..
+        } => FrameKind::Link(format!("mailto:{}", dest_url)),
+        Tag::Link { dest_url, .. } => FrameKind::Link(dest_url.to_string()),
+        Tag::Image { dest_url, .. } => FrameKind::Image(dest_url.to_string()),
+        // Emphasis and the like have no plain-text equivalent: their text is kept as is
+        _ => return None,
+    };
+    Some(kind)
+}
+
+fn has_frame(tag: TagEnd) -> bool {
+    matches!(
+        tag,
+        TagEnd::Paragraph
+            | TagEnd::HtmlBlock
+            | TagEnd::TableCell
+            | TagEnd::Heading(_)
+            | TagEnd::CodeBlock
+            | TagEnd::BlockQuote(_)
+            | TagEnd::List(_)
+            | TagEnd::Item
+            | TagEnd::Table
+            | TagEnd::TableHead
+            | TagEnd::TableRow
+            | TagEnd::Link
+            | TagEnd::Image
+    )
+}
+
+/// Walk the events with a stack rather than recursion: however deeply nested the
+/// quotes and lists of an issue are, they cannot overflow ours.
+fn render_text(events: Vec<Event<'_>>) -> String {
+    let mut footnotes = Vec::new();
+    let mut stack = vec![Frame::new(FrameKind::Document)];
+    for event in events {
+        match event {
+            Event::Start(tag) => {
+                if let Some(kind) = frame_kind(tag) {
+                    stack.push(Frame::new(kind));
+                }
+            }
+            Event::End(tag) if has_frame(tag) && stack.len() > 1 => {
+                let Some(frame) = stack.pop() else { break };
+                let Some(parent) = stack.last_mut() else {
+                    break;
+                };
+                finish(frame, parent, &mut footnotes);
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.text.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.text.push('\n');
+                }
+            }
+            Event::Rule => {
+                if let Some(frame) = stack.last_mut() {
+                    frame.push_block("----------".to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    let mut document = stack.swap_remove(0);
+    document.flush_text();
+    let mut text = document.blocks.join("\n\n");
+    if !footnotes.is_empty() {
+        text.push_str("\n\n");
+        let footnotes: Vec<String> = footnotes
+            .iter()
+            .enumerate()
+            .map(|(i, url)| format!("[{}] {}", i + 1, url))
+            .collect();
+        text.push_str(&footnotes.join("\n"));
+    }
+    text
+}
+
+/// Render `frame`, which just ended, into its `parent`.
+fn finish(mut frame: Frame, parent: &mut Frame, footnotes: &mut Vec<String>) {
+    let block = match frame.kind {
+        FrameKind::Link(href) => {
+            let label = frame.text;
+            parent.text.push_str(&label);
+            // Autolinks show their URL already
+            if href != label && href.strip_prefix("mailto:") != Some(label.as_str()) {
+                parent
+                    .text
+                    .push_str(&format!(" [{}]", footnote(footnotes, &href)));
+            }
+            return;
+        }
+        FrameKind::Image(src) => {
+            let alt = frame.text;
+            parent
+                .text
+                .push_str(&format!("{} [{}]", alt, footnote(footnotes, &src)));
+            return;
+        }
+        FrameKind::Document | FrameKind::Text | FrameKind::Item => {
+            frame.flush_text();
+            frame.blocks.join("\n")
+        }
+        FrameKind::Heading(level) => {
+            frame.flush_text();
+            let text = frame.blocks.join("\n");
+            let underline = match level {
+                HeadingLevel::H1 => "=",
+                HeadingLevel::H2 => "-",
+                _ => "",
+            };
+            let width = text.lines().map(|l| l.chars().count()).max().unwrap_or(0);
+            if underline.is_empty() {
+                text
+            } else {
+                format!("{}\n{}", text, underline.repeat(width))
+            }
+        }
+        FrameKind::Code => {
+            frame.flush_text();
+            prefix_lines(&frame.blocks.join("\n"), "    ", "    ")
+        }
+        FrameKind::Quote => {
+            frame.flush_text();
+            prefix_lines(&frame.blocks.join("\n\n"), "> ", "> ")
+        }
+        FrameKind::List(start) => {
+            let items: Vec<String> = frame
+                .blocks
+                .iter()
+                .enumerate()
+                .map(|(i, item)| {
+                    let marker = match start {
+                        None => "- ".to_string(),
+                        Some(start) => format!("{}. ", start + i as u64),
+                    };
+                    prefix_lines(item, &marker, &" ".repeat(marker.len()))
+                })
+                .collect();
+            items.join("\n")
+        }
+        FrameKind::Table => frame.blocks.join("\n"),
+        FrameKind::Row => frame.blocks.join(" | "),
+    };
+    parent.push_block(block);
+}
+
+/// The number of the footnote for `url`, which is added if it is not there yet.
+fn footnote(footnotes: &mut Vec<String>, url: &str) -> usize {
+    match footnotes.iter().position(|f| f == url) {
+        Some(i) => i + 1,
+        None => {
+            footnotes.push(url.to_string());
+            footnotes.len()
+        }
+    }
+}
+
+/// Prefix the first line of `text` with `first`, and every other one with `others`.
+fn prefix_lines(text: &str, first: &str, others: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { others };
+            if line.is_empty() {
+                prefix.trim_end().to_string()
+            } else {
+                format!("{}{}", prefix, line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn blocks_are_rendered_in_both_formats() {
+        let source = "# Issue #1\n\nHello **world**, this is *new*.\n\n\
+            - one\n- two\n  1. nested\n\n> quoted\n\n```\nlet x = 1 < 2;\n```\n\n---";
+
+        let rendered = render_markdown(source);
+
+        for fragment in [
+            "<h1>Issue #1</h1>",
+            "<p>Hello <strong>world</strong>, this is <em>new</em>.</p>",
+            "<li>one</li>",
+            "<li>nested</li>",
+            "<blockquote>\n<p>quoted</p>\n</blockquote>",
+            "<pre><code>let x = 1 &lt; 2;\n</code></pre>",
+            "<hr>",
+        ] {
+            assert!(rendered.html.contains(fragment), "{}", rendered.html);
+        }
+        assert_eq!(
+            rendered.text,
+            "Issue #1\n========\n\n\
+            Hello world, this is new.\n\n\
+            - one\n- two\n  1. nested\n\n\
+            > quoted\n\n    let x = 1 < 2;\n\n\
+            ----------"
+        );
+    }
+
+    #[test]
+    fn links_become_footnotes_in_the_text_version() {
+        let source = "Read [the post][post] and [again](https://example.com/post), \
+            then <https://example.com> or ![a cat](https://example.com/cat.png).\n\n\
+            [post]: https://example.com/post";
+
+        let rendered = render_markdown(source);
+
+        assert_eq!(
+            rendered.html,
+            "<p>Read <a href=\"https://example.com/post\">the post</a> and \
+            <a href=\"https://example.com/post\">again</a>, then \
+            <a href=\"https://example.com\">https://example.com</a> or \
+            <img src=\"https://example.com/cat.png\" alt=\"a cat\">.</p>\n"
+        );
+        assert_eq!(
+            rendered.text,
+            "Read the post [1] and again [1], then https://example.com or a cat [2].\n\n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/cat.png"
+        );
+    }
+
+    #[test]
+    fn raw_html_and_unsafe_links_are_not_rendered() {
+        let source = "Hi <script>alert(1)</script> [click](javascript:alert(1)) \
+            [\"quoted\"](https://example.com/?a=1&b=\"2\") <img src=x onerror=alert(1)>\n\n\
+            [relative](/admin) ![tracker](javascript:alert(1))";
+
+        let rendered = render_markdown(source);
+
+        assert!(!rendered.html.contains("<script"), "{}", rendered.html);
+        assert!(!rendered.html.contains("<img"), "{}", rendered.html);
+        assert!(!rendered.html.contains("javascript"), "{}", rendered.html);
+        assert!(!rendered.html.contains("/admin"), "{}", rendered.html);
+        assert!(
+            rendered.html.contains("&lt;script&gt;"),
+            "{}",
+            rendered.html
+        );
+        assert!(rendered.html.contains(" click "), "{}", rendered.html);
+        assert!(!rendered.text.contains("javascript"));
+        assert!(rendered.text.contains("\n\nrelative tracker\n\n"));
+    }
+
+    #[test]
+    fn setext_headings_and_tables_are_supported() {
+        let source = "Title\n=====\n\n| Name | Year |\n|------|------|\n| Dune | 1965 |";
+
+        let rendered = render_markdown(source);
+
+        assert!(
+            rendered.html.contains("<h1>Title</h1>"),
+            "{}",
+            rendered.html
+        );
+        assert!(rendered.html.contains("<td>Dune</td>"), "{}", rendered.html);
+        assert_eq!(rendered.text, "Title\n=====\n\nName | Year\nDune | 1965");
+    }
+
+    #[test]
+    fn line_breaks_are_kept() {
+        let rendered = render_markdown("first  \nsecond\nthird");
+
+        assert_eq!(rendered.html, "<p>first<br>\nsecond\nthird</p>\n");
+        assert_eq!(rendered.text, "first\nsecond\nthird");
+    }
+
+    #[test]
+    fn deeply_nested_blocks_do_not_overflow_the_stack() {
+        let quotes = format!("{}deep", ">".repeat(10_000));
+        let lists: String = (0..1_000)
+            .map(|depth| format!("{}- deep\n", "  ".repeat(depth)))
+            .collect();
+
+        for source in [quotes, lists] {
+            let rendered = render_markdown(&source);
+
+            assert!(rendered.text.contains("deep"));
+        }
+    }
+}
@@ -0,0 +1,439 @@
+//! src/templates/mod.rs
+
+mod catalog;
+mod markdown;
+mod template;
+
+pub use markdown::{RenderedMarkdown, render_markdown};
+pub use template::{Escaping, Template, TemplateError};
+
+use anyhow::Context;
+use catalog::Catalog;
+use std::collections::HashMap;
+use std::path::Path;
+use url::Url;
+
+/// The subject, HTML and plain-text versions of an email, ready to be sent.
+#[derive(serde::Serialize)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+/// An email the application sends, and what its templates can refer to.
+#[derive(Debug)]
+struct Definition {
+    name: &'static str,
+    html: &'static str,
+    text: &'static str,
+    /// Provided by the code sending the email.
+    variables: &'static [&'static str],
+    /// Looked up in the catalog of the recipient's locale.
+    messages: &'static [&'static str],
+}
+
+const CONFIRMATION: Definition = Definition {
+    name: "confirmation",
+    html: include_str!("../../templates/confirmation.html"),
+    text: include_str!("../../templates/confirmation.txt"),
+    variables: &["name", "confirmation_link"],
+    messages: &["subject", "greeting", "call_to_action", "ignore"],
+};
+
+const WELCOME: Definition = Definition {
+    name: "welcome",
+    html: include_str!("../../templates/welcome.html"),
+    text: include_str!("../../templates/welcome.txt"),
+    variables: &["name", "unsubscribe_link"],
+    messages: &[
+        "subject",
+        "thanks",
+        "next_issue",
+        "unsubscribe_prompt",
+        "unsubscribe",
+    ],
+};
+
+/// The subject is the title of the issue.
+const NEWSLETTER: Definition = Definition {
+    name: "newsletter",
+    html: include_str!("../../templates/newsletter.html"),
+    text: include_str!("../../templates/newsletter.txt"),
+    variables: &["title", "content", "unsubscribe_link"],
+    messages: &["footer", "unsubscribe"],
+};
+
+const DEFINITIONS: &[&Definition] = &[&CONFIRMATION, &WELCOME, &NEWSLETTER];
+
+/// Built into the binary, by locale.
+const BUILT_IN_CATALOGS: &[(&str, &str)] = &[
+    ("de", include_str!("../../templates/locales/de.yaml")),
+    ("en", include_str!("../../templates/locales/en.yaml")),
+    ("es", include_str!("../../templates/locales/es.yaml")),
+    ("fr", include_str!("../../templates/locales/fr.yaml")),
+];
+
+/// Every email the application sends, in every locale we have a catalog for,
+/// compiled once at startup.
+///
+/// The templates in `templates/` and the catalogs in `templates/locales/` are built into
+/// the binary; a deployment can replace any of them, or add a catalog for a new locale,
+/// with a file of the same name in its own directory.
+#[derive(Debug)]
+pub struct EmailTemplates {
+    default_locale: String,
+    catalogs: HashMap<String, Catalog>,
+    confirmation: EmailTemplate,
+    welcome: EmailTemplate,
+    newsletter: EmailTemplate,
+}
+
+impl EmailTemplates {
+    /// Fails if a file in `directory` cannot be read or is not valid,
+    /// or if the catalog of `default_locale` is missing a message.
+    pub fn load(directory: Option<&Path>, default_locale: &str) -> Result<Self, anyhow::Error> {
+        let catalogs = load_catalogs(directory)?;
+        let default_catalog = catalogs.get(default_locale).with_context(|| {
+            format!(
+                "There is no catalog for the default locale `{}`.",
+                default_locale
+            )
+        })?;
+        // Every other locale falls back on it
+        for definition in DEFINITIONS {
+            for key in definition.messages {
+                if default_catalog.message(definition.name, key).is_none() {
+                    anyhow::bail!(
+                        "The catalog of the default locale `{}` is missing `{}.{}`.",
+                        default_locale,
+                        definition.name,
+                        key
+                    );
+                }
+            }
+        }
+        Ok(Self {
+            default_locale: default_locale.to_string(),
+            catalogs,
+            confirmation: EmailTemplate::load(directory, &CONFIRMATION)?,
+            welcome: EmailTemplate::load(directory, &WELCOME)?,
+            newsletter: EmailTemplate::load(directory, &NEWSLETTER)?,
+        })
+    }
+
+    /// The first of the `preferences` we have a catalog for, or the default locale.
+    ///
+    /// A regional preference (`fr-CA`) is served the catalog of its language (`fr`)
+    /// if there is none for the region.
+    pub fn negotiate_locale<'a>(&self, preferences: impl IntoIterator<Item = &'a str>) -> String {
+        for preference in preferences {
+            let preference = preference.trim().replace('_', "-").to_ascii_lowercase();
+            if self.catalogs.contains_key(&preference) {
+                return preference;
+            }
+            if let Some((language, _)) = preference.split_once('-')
+                && self.catalogs.contains_key(language)
+            {
+                return language.to_string();
+            }
+        }
+        self.default_locale.clone()
+    }
+
+    /// Asks a new subscriber to confirm their address.
+    pub fn confirmation(
+        &self,
+        locale: Option<&str>,
+        name: &str,
+        confirmation_link: &Url,
+    ) -> RenderedEmail {
+        let values = [
+            ("name", name),
+            ("confirmation_link", confirmation_link.as_str()),
+        ];
+        self.render(&self.confirmation, locale, &values, &values)
+    }
+
+    /// Sent once a subscription is confirmed.
+    pub fn welcome(
+        &self,
+        locale: Option<&str>,
+        name: &str,
+        unsubscribe_link: &Url,
+    ) -> RenderedEmail {
+        let values = [
+            ("name", name),
+            ("unsubscribe_link", unsubscribe_link.as_str()),
+        ];
+        self.render(&self.welcome, locale, &values, &values)
+    }
+
+    /// Wraps the content of an issue, which comes in both formats already.
+    pub fn newsletter(
+        &self,
+        locale: Option<&str>,
+        title: &str,
+        html_content: &str,
+        text_content: &str,
+        unsubscribe_link: &Url,
+    ) -> RenderedEmail {
+        let unsubscribe_link = unsubscribe_link.as_str();
+        let email = self.render(
+            &self.newsletter,
+            locale,
+            &[
+                ("title", title),
+                ("content", html_content),
+                ("unsubscribe_link", unsubscribe_link),
+            ],
+            &[
+                ("title", title),
+                ("content", text_content),
+                ("unsubscribe_link", unsubscribe_link),
+            ],
+        );
+        RenderedEmail {
+            subject: title.to_string(),
+            ..email
+        }
+    }
+
+    /// Render `template` in `locale`, or in the default locale if we do not have it (or `None`).
+    fn render(
+        &self,
+        template: &EmailTemplate,
+        locale: Option<&str>,
+        html_values: &[(&str, &str)],
+        text_values: &[(&str, &str)],
+    ) -> RenderedEmail {
+        let locale = locale
+            .filter(|locale| self.catalogs.contains_key(*locale))
+            .unwrap_or(&self.default_locale);
+        // Messages are plain text: the HTML template escapes them like any other value
+        let messages: Vec<(&str, String)> = template
+            .definition
+            .messages
+            .iter()
+            .map(|key| {
+                (
+                    *key,
+                    self.message(locale, template.definition.name, key)
+                        .render(text_values),
+                )
+            })
+            .collect();
+        let mut html_values = html_values.to_vec();
+        let mut text_values = text_values.to_vec();
+        for values in [&mut html_values, &mut text_values] {
+            values.push(("locale", locale));
+            values.extend(
+                messages
+                    .iter()
+                    .map(|(key, message)| (*key, message.as_str())),
+            );
+        }
+        let subject = messages
+            .iter()
+            .find(|(key, _)| *key == "subject")
+            .map(|(_, subject)| subject.clone())
+            .unwrap_or_default();
+        RenderedEmail {
+            subject,
+            html: template.html.render(&html_values),
+            text: template.text.render(&text_values),
+        }
+    }
+
+    fn message(&self, locale: &str, email: &str, key: &str) -> &Template {
+        self.catalogs
+            .get(locale)
+            .and_then(|catalog| catalog.message(email, key))
+            .or_else(|| self.catalogs[&self.default_locale].message(email, key))
+            .expect("The default catalog is checked to be complete when loading.")
+    }
+}
+
+#[derive(Debug)]
+struct EmailTemplate {
+    definition: &'static Definition,
+    html: Template,
+    text: Template,
+}
+
+impl EmailTemplate {
+    /// Compile `<name>.html` and `<name>.txt`, from `directory` if it has them.
+    fn load(
+        directory: Option<&Path>,
+        definition: &'static Definition,
+    ) -> Result<Self, anyhow::Error> {
+        let name = definition.name;
+        let html = load_file(directory, &format!("{}.html", name), definition.html)?;
+        let text = load_file(directory, &format!("{}.txt", name), definition.text)?;
+        let variables: Vec<&str> = ["locale"]
+            .iter()
+            .chain(definition.variables)
+            .chain(definition.messages)
+            .copied()
+            .collect();
+        Ok(Self {
+            definition,
+            html: Template::compile(&html, Escaping::Html, &variables)
+                .with_context(|| format!("Invalid email template `{}.html`.", name))?,
+            text: Template::compile(&text, Escaping::None, &variables)
+                .with_context(|| format!("Invalid email template `{}.txt`.", name))?,
+        })
+    }
+}
+
+/// The built-in catalogs, replaced or completed by the `locales/<locale>.yaml` files in `directory`.
+fn load_catalogs(directory: Option<&Path>) -> Result<HashMap<String, Catalog>, anyhow::Error> {
+    let mut sources: HashMap<String, String> = BUILT_IN_CATALOGS
+        .iter()
+        .map(|(locale, source)| (locale.to_string(), source.to_string()))
+        .collect();
+    if let Some(locales) = directory.map(|directory| directory.join("locales"))
+        && locales.is_dir()
+    {
+        let entries = std::fs::read_dir(&locales)
+            .with_context(|| format!("Failed to list the catalogs in {}.", locales.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "yaml") {
+                continue;
+            }
+            let Some(locale) = path.file_stem().and_then(|stem| stem.to_str()) else {
+                continue;
+            };
+            tracing::info!(path = %path.display(), "Using a custom message catalog");
+            let source = std::fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read the catalog at {}.", path.display()))?;
+            sources.insert(locale.to_ascii_lowercase(), source);
+        }
+    }
+    sources
+        .into_iter()
+        .map(|(locale, source)| {
+            let catalog = Catalog::parse(&source, DEFINITIONS)
+                .with_context(|| format!("Invalid catalog for the `{}` locale.", locale))?;
+            Ok((locale, catalog))
+        })
+        .collect()
+}
+
+fn load_file(
+    directory: Option<&Path>,
+    file_name: &str,
+    built_in: &str,
+) -> Result<String, anyhow::Error> {
+    let Some(path) = directory
+        .map(|directory| directory.join(file_name))
+        .filter(|path| path.exists())
+    else {
+        return Ok(built_in.to_string());
+    };
+    tracing::info!(path = %path.display(), "Using a custom email template");
+    std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read the email template at {}.", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailTemplates;
+    use claims::{assert_err, assert_ok};
+    use url::Url;
+
+    fn templates() -> EmailTemplates {
+        EmailTemplates::load(None, "en").unwrap()
+    }
+
+    #[test]
+    fn built_in_templates_are_valid() {
+        assert_ok!(EmailTemplates::load(None, "en"));
+        assert_ok!(EmailTemplates::load(None, "fr"));
+        assert_err!(EmailTemplates::load(None, "xx"));
+    }
+
+    #[test]
+    fn confirmation_bodies_are_in_the_right_format() {
+        let link = Url::parse("https://example.com/subscriptions/confirm?token=abc").unwrap();
+
+        let email = templates().confirmation(Some("en"), "<Ursula>", &link);
+
+        assert_eq!(email.subject, "Welcome!");
+        assert!(
+            email
+                .html
+                .contains(r#"<a href="https://example.com/subscriptions/confirm?token=abc">"#)
+        );
+        assert!(email.html.contains("&lt;Ursula&gt;"));
+        assert!(!email.text.contains("<a"));
+        assert!(email.text.contains("<Ursula>"));
+    }
+
+    #[test]
+    fn emails_are_rendered_in_the_recipient_locale_or_the_default_one() {
+        let link = Url::parse("https://example.com/subscriptions/confirm?token=abc").unwrap();
+        let templates = templates();
+
+        let french = templates.confirmation(Some("fr"), "Ursula", &link);
+        let unknown = templates.confirmation(Some("xx"), "Ursula", &link);
+        let unset = templates.confirmation(None, "Ursula", &link);
+
+        assert_eq!(french.subject, "Bienvenue !");
+        assert!(french.html.contains(r#"<html lang="fr">"#));
+        assert!(
+            french
+                .text
+                .contains("Bienvenue dans notre newsletter, Ursula !")
+        );
+        for email in [unknown, unset] {
+            assert_eq!(email.subject, "Welcome!");
+            assert!(email.html.contains(r#"<html lang="en">"#));
+        }
+    }
+
+    #[test]
+    fn the_first_supported_preference_wins() {
+        let templates = templates();
+
+        assert_eq!(templates.negotiate_locale(["fr"]), "fr");
+        assert_eq!(templates.negotiate_locale(["fr-CA", "de"]), "fr");
+        assert_eq!(templates.negotiate_locale(["pt_BR", "de-DE"]), "de");
+        assert_eq!(templates.negotiate_locale(["pt", "xx"]), "en");
+        assert_eq!(templates.negotiate_locale([]), "en");
+    }
+
+    #[test]
+    fn files_in_the_directory_replace_the_built_in_ones() {
+        let directory = std::env::temp_dir().join(format!("templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(directory.join("locales")).unwrap();
+        std::fs::write(directory.join("welcome.txt"), "{{ thanks }}").unwrap();
+        // Only some messages: the rest comes from the default locale
+        std::fs::write(
+            directory.join("locales/it.yaml"),
+            "welcome:\n  thanks: \"Grazie, {{ name }}!\"\n",
+        )
+        .unwrap();
+        let templates = EmailTemplates::load(Some(&directory), "en").unwrap();
+        let link = Url::parse("https://example.com/unsubscribe").unwrap();
+
+        let email = templates.welcome(Some("it"), "Ursula", &link);
+
+        assert_eq!(email.text, "Grazie, Ursula!");
+        assert_eq!(email.subject, "You're subscribed!");
+        // The other format keeps the built-in template
+        assert!(email.html.contains("https://example.com/unsubscribe"));
+
+        std::fs::write(directory.join("welcome.html"), "{{ nmae }}").unwrap();
+        assert_err!(EmailTemplates::load(Some(&directory), "en"));
+        std::fs::write(directory.join("welcome.html"), "{{ thanks }}").unwrap();
+        std::fs::write(
+            directory.join("locales/it.yaml"),
+            "welcome:\n  thank: \"Grazie\"\n",
+        )
+        .unwrap();
+        assert_err!(EmailTemplates::load(Some(&directory), "en"));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,188 @@
+/// How values are written into the output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Escaping {
+    /// HTML-escape values, unless the tag asks for them `raw`.
+    Html,
+    /// Plain text: values are written as they are.
+    None,
+}
+
+/// A template parsed once, when it is loaded, and rendered many times.
+///
+/// The syntax is deliberately small: `{{ variable }}` is replaced by the value of `variable`,
+/// and `{{ variable | raw }}` skips escaping, for values which are HTML already.
+#[derive(Debug, Clone)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Value { name: String, escape: bool },
+}
+
+#[derive(thiserror::Error, Debug, PartialEq)]
+pub enum TemplateError {
+    #[error("Line {line}: `{{{{` is never closed.")]
+    UnclosedTag { line: usize },
+    #[error("Line {line}: unknown variable `{name}`, expected one of: {expected}.")]
+    UnknownVariable {
+        line: usize,
+        name: String,
+        expected: String,
+    },
+    #[error("Line {line}: unknown filter `{filter}`, the only one is `raw`.")]
+    UnknownFilter { line: usize, filter: String },
+}
+
+impl Template {
+    /// Parse `source`, making sure it only refers to the given `variables`:
+    /// a typo in a template is caught at startup rather than when an email goes out.
+    pub fn compile(
+        source: &str,
+        escaping: Escaping,
+        variables: &[&str],
+    ) -> Result<Self, TemplateError> {
+        let mut segments = Vec::new();
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            let line = source[..source.len() - rest.len() + start]
+                .matches('\n')
+                .count()
+                + 1;
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+            let after_open = &rest[start + 2..];
+            let end = after_open
+                .find("}}")
+                .ok_or(TemplateError::UnclosedTag { line })?;
+            let mut parts = after_open[..end].split('|').map(str::trim);
+            let name = parts.next().unwrap_or_default();
+            if !variables.contains(&name) {
+                return Err(TemplateError::UnknownVariable {
+                    line,
+                    name: name.to_string(),
+                    expected: variables.join(", "),
+                });
+            }
+            let mut escape = escaping == Escaping::Html;
+            for filter in parts {
+                match filter {
+                    "raw" => escape = false,
+                    filter => {
+                        return Err(TemplateError::UnknownFilter {
+                            line,
+                            filter: filter.to_string(),
+                        });
+                    }
+                }
+            }
+            segments.push(Segment::Value {
+                name: name.to_string(),
+                escape,
+            });
+            rest = &after_open[end + 2..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+        Ok(Self { segments })
+    }
+
+    /// `values` must cover the variables the template was compiled with.
+    pub fn render(&self, values: &[(&str, &str)]) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => output.push_str(text),
+                Segment::Value { name, escape } => {
+                    let value = values
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, value)| *value)
+                        .unwrap_or_default();
+                    if *escape {
+                        output.push_str(&htmlescape::encode_minimal(value));
+                    } else {
+                        output.push_str(value);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Escaping, Template, TemplateError};
+    use claims::{assert_err_eq, assert_ok};
+
+    #[test]
+    fn variables_are_replaced_by_their_value() {
+        let template = assert_ok!(Template::compile(
+            "Hello {{name}}, {{ greeting }}!",
+            Escaping::None,
+            &["name", "greeting"],
+        ));
+
+        let output = template.render(&[("greeting", "welcome"), ("name", "Ursula")]);
+
+        assert_eq!(output, "Hello Ursula, welcome!");
+    }
+
+    #[test]
+    fn values_are_escaped_in_html_unless_raw() {
+        let template = assert_ok!(Template::compile(
+            "<p>{{ name }}</p>{{ content | raw }}",
+            Escaping::Html,
+            &["name", "content"],
+        ));
+
+        let output = template.render(&[
+            ("name", "<script>alert(\"hi\")</script> & co"),
+            ("content", "<b>bold</b>"),
+        ]);
+
+        assert_eq!(
+            output,
+            "<p>&lt;script&gt;alert(&quot;hi&quot;)&lt;/script&gt; &amp; co</p><b>bold</b>"
+        );
+    }
+
+    #[test]
+    fn text_templates_are_not_escaped() {
+        let template = assert_ok!(Template::compile("{{ name }}", Escaping::None, &["name"]));
+
+        assert_eq!(template.render(&[("name", "Tom & Jerry")]), "Tom & Jerry");
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        assert_err_eq!(
+            Template::compile("Hi\n{{ nmae }}", Escaping::None, &["name"]),
+            TemplateError::UnknownVariable {
+                line: 2,
+                name: "nmae".into(),
+                expected: "name".into(),
+            }
+        );
+    }
+
+    #[test]
+    fn unclosed_tags_and_unknown_filters_are_rejected() {
+        assert_err_eq!(
+            Template::compile("Hi {{ name", Escaping::None, &["name"]),
+            TemplateError::UnclosedTag { line: 1 }
+        );
+        assert_err_eq!(
+            Template::compile("{{ name | upper }}", Escaping::Html, &["name"]),
+            TemplateError::UnknownFilter {
+                line: 1,
+                filter: "upper".into(),
+            }
+        );
+    }
+}
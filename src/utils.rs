@@ -0,0 +1,26 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+
+/// Walk the `source()` chain of an error, printing every cause on its own line.
+/// Used to implement `Debug` for our error types so logs carry the full context.
+pub fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+/// Log an unexpected error and turn it into an opaque `500 Internal Server Error`.
+pub fn e500<T>(e: T) -> Response
+where
+    T: std::fmt::Debug + std::fmt::Display,
+{
+    tracing::error!(error = ?e, "{}", e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
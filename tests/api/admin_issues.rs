@@ -0,0 +1,387 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app, spawn_app_with};
+use axum::http::StatusCode;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_draft(test_app: &TestApp) -> String {
+    let issue: serde_json::Value = test_app
+        .post_admin_issues(&serde_json::json!({
+            "title": "Issue #1",
+            "content": {"markdown": "Hello **world**, read [this](https://example.com/post)."}
+        }))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    issue["newsletter_issue_id"].as_str().unwrap().to_owned()
+}
+
+async fn insert_confirmed_subscriber(test_app: &TestApp) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula@example.com', 'le guin', now(), 'confirmed')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_issues() {
+    let test_app = spawn_app().await;
+    let issue_id = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({"title": "Issue #1", "content": {"markdown": "Hello"}});
+
+    let responses = [
+        test_app.get_admin_issues().await,
+        test_app.post_admin_issues(&body).await,
+        test_app.put_admin_issue(&issue_id, &body).await,
+        test_app.get_admin_issue_preview(&issue_id, "").await,
+        test_app
+            .post_admin_issue_test(&issue_id, &serde_json::json!({}))
+            .await,
+        test_app.post_admin_issue_publish(&issue_id).await,
+        test_app
+            .put_admin_issue_schedule(&issue_id, &serde_json::json!({}))
+            .await,
+        test_app.delete_admin_issue_schedule(&issue_id).await,
+    ];
+
+    for response in responses {
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn drafts_can_be_created_updated_and_listed_without_sending_anything() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    insert_confirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Create
+    let issue_id = create_draft(&test_app).await;
+
+    // Act - Part 2 - Update
+    let response = test_app
+        .put_admin_issue(
+            &issue_id,
+            &serde_json::json!({
+                "title": "Issue #1, take two",
+                "content": {"text": "Hello world", "html": "<p>Hello world</p>"}
+            }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["title"], "Issue #1, take two");
+    assert_eq!(issue["status"], "draft");
+    assert_eq!(issue["html_content"], "<p>Hello world</p>");
+    assert!(issue["markdown_content"].is_null());
+    assert!(issue["published_at"].is_null());
+    let list: serde_json::Value = test_app.get_admin_issues().await.json().await.unwrap();
+    assert_eq!(list["issues"][0]["newsletter_issue_id"], issue_id.as_str());
+    assert_eq!(list["issues"][0]["status"], "draft");
+    test_app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn previews_render_the_issue_like_subscribers_get_it() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    let issue_id = create_draft(&test_app).await;
+
+    // Act
+    let response = test_app
+        .get_admin_issue_preview(&issue_id, "locale=fr")
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let preview: serde_json::Value = response.json().await.unwrap();
+    let html = preview["html"].as_str().unwrap();
+    let text = preview["text"].as_str().unwrap();
+    assert_eq!(preview["subject"], "Issue #1");
+    assert!(html.contains(r#"<html lang="fr">"#));
+    assert!(html.contains("Hello <strong>world</strong>"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    assert!(text.starts_with("Hello world, read this [1].\n\n[1] https://example.com/post"));
+}
+
+#[tokio::test]
+async fn test_sends_only_go_to_the_configured_test_recipients() {
+    // Arrange
+    let test_app = spawn_app_with(|c| {
+        c.newsletter_issues.test_recipients = vec!["editor@example.com".into()];
+    })
+    .await;
+    test_app.login_test_user().await;
+    insert_confirmed_subscriber(&test_app).await;
+    let issue_id = create_draft(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Someone else
+    let rejected = test_app
+        .post_admin_issue_test(
+            &issue_id,
+            &serde_json::json!({"recipients": ["ursula@example.com"]}),
+        )
+        .await;
+
+    // Act - Part 2 - Every test recipient
+    let sent = test_app
+        .post_admin_issue_test(&issue_id, &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, rejected.status());
+    let problem: serde_json::Value = rejected.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["code"], "not_a_test_recipient");
+    assert_eq!(StatusCode::NO_CONTENT, sent.status());
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(email["To"], "editor@example.com");
+    assert_eq!(email["Subject"], "[Test] Issue #1");
+    // Nothing was queued for subscribers
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_and_can_no_longer_be_edited() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    insert_confirmed_subscriber(&test_app).await;
+    let issue_id = create_draft(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish
+    let response = test_app.post_admin_issue_publish(&issue_id).await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 1
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+    assert!(issue["published_at"].is_string());
+
+    // Act - Part 2 - Edit or publish again
+    let update = test_app
+        .put_admin_issue(
+            &issue_id,
+            &serde_json::json!({"title": "Too late", "content": {"markdown": "Oops"}}),
+        )
+        .await;
+    let publish = test_app.post_admin_issue_publish(&issue_id).await;
+    let unknown = test_app
+        .post_admin_issue_publish(&uuid::Uuid::new_v4().to_string())
+        .await;
+
+    // Assert - Part 2
+    assert_eq!(StatusCode::CONFLICT, update.status());
+    assert_eq!(StatusCode::CONFLICT, publish.status());
+    assert_eq!(StatusCode::NOT_FOUND, unknown.status());
+}
+
+/// Pretend that the scheduled time of every issue has come.
+async fn make_scheduled_issues_due(test_app: &TestApp) {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_at = now() - interval '1 minute'
+        WHERE status = 'scheduled'
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+}
+
+fn in_one_hour() -> String {
+    (chrono::Utc::now() + chrono::TimeDelta::hours(1)).to_rfc3339()
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_once_when_they_are_due() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    insert_confirmed_subscriber(&test_app).await;
+    let issue_id = create_draft(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Schedule, then run the scheduler before the issue is due
+    let response = test_app
+        .put_admin_issue_schedule(
+            &issue_id,
+            &serde_json::json!({"scheduled_at": in_one_hour()}),
+        )
+        .await;
+    test_app.send_due_issues().await;
+
+    // Assert - Part 1
+    assert_eq!(StatusCode::OK, response.status());
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+
+    // Act - Part 2 - Several replicas find it due at the same time
+    make_scheduled_issues_due(&test_app).await;
+    tokio::join!(
+        test_app.send_due_issues(),
+        test_app.send_due_issues(),
+        test_app.send_due_issues()
+    );
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let issue = sqlx::query!("SELECT status, published_at FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "sent");
+    assert!(issue.published_at.is_some());
+    // Mock verifies on drop that the subscriber got the issue once
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled_or_cancelled_until_they_are_sent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    insert_confirmed_subscriber(&test_app).await;
+    let issue_id = create_draft(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Schedule, then reschedule
+    let tomorrow = chrono::Utc::now() + chrono::TimeDelta::days(1);
+    test_app
+        .put_admin_issue_schedule(
+            &issue_id,
+            &serde_json::json!({"scheduled_at": in_one_hour()}),
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+    let rescheduled = test_app
+        .put_admin_issue_schedule(
+            &issue_id,
+            &serde_json::json!({"scheduled_at": tomorrow.to_rfc3339()}),
+        )
+        .await;
+
+    // Assert - Part 1
+    assert_eq!(StatusCode::OK, rescheduled.status());
+    let issue: serde_json::Value = rescheduled.json().await.unwrap();
+    let scheduled_at: chrono::DateTime<chrono::Utc> =
+        serde_json::from_value(issue["scheduled_at"].clone()).unwrap();
+    assert_eq!(scheduled_at.timestamp_micros(), tomorrow.timestamp_micros());
+
+    // Act - Part 2 - Cancel, twice
+    let cancelled = test_app.delete_admin_issue_schedule(&issue_id).await;
+    let again = test_app.delete_admin_issue_schedule(&issue_id).await;
+    make_scheduled_issues_due(&test_app).await;
+    test_app.send_due_issues().await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    assert_eq!(StatusCode::OK, cancelled.status());
+    let issue: serde_json::Value = cancelled.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+    assert!(issue["scheduled_at"].is_null());
+    assert_eq!(StatusCode::CONFLICT, again.status());
+    // Mock verifies on drop that nothing was sent
+}
+
+#[tokio::test]
+async fn issues_cannot_be_scheduled_in_the_past_or_once_sent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    let issue_id = create_draft(&test_app).await;
+    let yesterday = chrono::Utc::now() - chrono::TimeDelta::days(1);
+
+    // Act - Part 1 - In the past
+    let past = test_app
+        .put_admin_issue_schedule(
+            &issue_id,
+            &serde_json::json!({"scheduled_at": yesterday.to_rfc3339()}),
+        )
+        .await;
+
+    // Assert - Part 1
+    assert_eq!(StatusCode::BAD_REQUEST, past.status());
+    let problem: serde_json::Value = past.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "scheduled_at");
+    assert_eq!(problem["invalid-params"][0]["code"], "in_the_past");
+
+    // Act - Part 2 - Once sent
+    test_app
+        .post_admin_issue_publish(&issue_id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let schedule = test_app
+        .put_admin_issue_schedule(
+            &issue_id,
+            &serde_json::json!({"scheduled_at": in_one_hour()}),
+        )
+        .await;
+    let cancel = test_app.delete_admin_issue_schedule(&issue_id).await;
+
+    // Assert - Part 2
+    assert_eq!(StatusCode::CONFLICT, schedule.status());
+    assert_eq!(StatusCode::CONFLICT, cancel.status());
+}
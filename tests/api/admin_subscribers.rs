@@ -0,0 +1,184 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+use axum::http::StatusCode;
+use chrono::{DateTime, TimeDelta, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+async fn insert_subscriber(
+    db_pool: &PgPool,
+    email: &str,
+    name: &str,
+    status: &str,
+    at: DateTime<Utc>,
+) {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        email,
+        name,
+        at,
+        status
+    )
+    .execute(db_pool)
+    .await
+    .expect("Failed to insert subscriber.");
+}
+
+fn day(n: i64) -> DateTime<Utc> {
+    DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+        .unwrap()
+        .to_utc()
+        + TimeDelta::days(n)
+}
+
+/// Five subscribers, one per day, in a mix of states.
+async fn spawn_app_with_subscribers() -> TestApp {
+    let test_app = spawn_app().await;
+    let subscribers = [
+        ("ursula@example.com", "Ursula Le Guin", "confirmed"),
+        ("octavia@example.com", "Octavia Butler", "confirmed"),
+        ("iain@example.com", "Iain Banks", "pending_confirmation"),
+        ("ted@example.com", "Ted Chiang", "unsubscribed"),
+        ("becky@example.com", "Becky Chambers", "confirmed"),
+    ];
+    for (i, (email, name, status)) in subscribers.into_iter().enumerate() {
+        insert_subscriber(&test_app.db_pool, email, name, status, day(i as i64)).await;
+    }
+    test_app.login_test_user().await;
+    test_app
+}
+
+async fn list(test_app: &TestApp, query: &str) -> serde_json::Value {
+    let response = test_app.get_admin_subscribers(query).await;
+    assert_eq!(StatusCode::OK, response.status());
+    response.json().await.unwrap()
+}
+
+fn emails(page: &serde_json::Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_list_subscribers() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_admin_subscribers("").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_are_paginated_most_recent_first() {
+    // Arrange
+    let test_app = spawn_app_with_subscribers().await;
+
+    // Act - walk through every page
+    let mut seen = Vec::new();
+    let mut query = "limit=2".to_string();
+    loop {
+        let page = list(&test_app, &query).await;
+        assert_eq!(page["total"], 5);
+        seen.extend(emails(&page).into_iter().map(str::to_owned));
+        match page["next_cursor"].as_str() {
+            Some(cursor) => query = format!("limit=2&cursor={}", cursor),
+            None => break,
+        }
+    }
+
+    // Assert
+    assert_eq!(
+        seen,
+        [
+            "becky@example.com",
+            "ted@example.com",
+            "iain@example.com",
+            "octavia@example.com",
+            "ursula@example.com",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn subscribers_can_be_filtered() {
+    // Arrange
+    let test_app = spawn_app_with_subscribers().await;
+    let test_cases = [
+        (
+            "status=confirmed".to_string(),
+            vec![
+                "becky@example.com",
+                "octavia@example.com",
+                "ursula@example.com",
+            ],
+        ),
+        // Matches the email address and the name, case-insensitively
+        (
+            "search=CH".to_string(),
+            vec!["becky@example.com", "ted@example.com"],
+        ),
+        ("search=ursula%40".to_string(), vec!["ursula@example.com"]),
+        // Wildcards are matched literally
+        ("search=%25".to_string(), vec![]),
+        (
+            format!(
+                "subscribed_after={}&subscribed_before={}",
+                day(1).to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+                day(3).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+            ),
+            vec!["iain@example.com", "octavia@example.com"],
+        ),
+        (
+            "status=unsubscribed&search=ch".to_string(),
+            vec!["ted@example.com"],
+        ),
+    ];
+
+    for (query, expected) in test_cases {
+        // Act
+        let page = list(&test_app, &query).await;
+
+        // Assert
+        assert_eq!(emails(&page), expected, "Unexpected page for `{}`", query);
+        assert_eq!(
+            page["total"],
+            expected.len(),
+            "Unexpected total for `{}`",
+            query
+        );
+    }
+}
+
+#[tokio::test]
+async fn invalid_parameters_are_rejected_with_400() {
+    // Arrange
+    let test_app = spawn_app_with_subscribers().await;
+    let test_cases = [
+        ("status=maybe", "status"),
+        ("limit=0", "limit"),
+        ("limit=100000", "limit"),
+        ("cursor=not-a-cursor", "cursor"),
+    ];
+
+    for (query, name) in test_cases {
+        // Act
+        let response = test_app.get_admin_subscribers(query).await;
+
+        // Assert
+        assert_eq!(
+            StatusCode::BAD_REQUEST,
+            response.status(),
+            "For `{}`",
+            query
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["invalid-params"][0]["name"], name);
+    }
+}
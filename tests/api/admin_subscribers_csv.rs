@@ -0,0 +1,379 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use axum::http::StatusCode;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::routes::MAX_IMPORT_SIZE;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_export_or_import_subscribers() {
+    let test_app = spawn_app().await;
+
+    let export = test_app.get_admin_subscribers_export().await;
+    let import = test_app
+        .post_admin_subscribers_import("confirmed", "email,name\nursula@example.com,Ursula\n")
+        .await;
+
+    assert_is_redirect_to(&export, "/login");
+    assert_is_redirect_to(&import, "/login");
+}
+
+#[tokio::test]
+async fn exported_subscribers_include_status_and_timestamps() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    test_app
+        .post_admin_subscribers_import(
+            "confirmed",
+            "email,name,consented_at,locale\n\
+            ursula@example.com,\"Le Guin, Ursula\",2025-03-01T12:00:00Z,fr-FR\n\
+            octavia@example.com,Octavia Butler,,\n",
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = test_app.get_admin_subscribers_export().await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let body = response.text().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec![
+            "id",
+            "email",
+            "name",
+            "status",
+            "subscribed_at",
+            "consented_at",
+            "consent_source",
+            "locale"
+        ]
+    );
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 2);
+    let ursula = rows.iter().find(|r| &r[1] == "ursula@example.com").unwrap();
+    assert_eq!(&ursula[2], "Le Guin, Ursula");
+    assert_eq!(&ursula[3], "confirmed");
+    assert_eq!(&ursula[5], "2025-03-01T12:00:00Z");
+    assert_eq!(&ursula[6], "import");
+    assert_eq!(&ursula[7], "fr");
+    let octavia = rows
+        .iter()
+        .find(|r| &r[1] == "octavia@example.com")
+        .unwrap();
+    assert!(!octavia[4].is_empty());
+    // Consent defaults to the time of the import
+    assert!(!octavia[5].is_empty());
+    assert_eq!(&octavia[7], "en");
+}
+
+#[tokio::test]
+async fn exported_names_cannot_run_as_spreadsheet_formulas() {
+    // Arrange - signups from the public form, which lets these names through
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    for body in [
+        "name=%3D1%2B2&email=ursula%40example.com",
+        "name=%40SUM%20A1&email=octavia%40example.com",
+        "name=Mary%20-%20Shelley&email=mary%40example.com",
+    ] {
+        test_app
+            .post_subscriptions(body.into())
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    test_app.login_test_user().await;
+
+    // Act
+    let response = test_app.get_admin_subscribers_export().await;
+
+    // Assert
+    let body = response.text().await.unwrap();
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    let name_of = |email: &str| rows.iter().find(|r| &r[1] == email).unwrap()[2].to_owned();
+    assert_eq!(name_of("ursula@example.com"), "'=1+2");
+    assert_eq!(name_of("octavia@example.com"), "'@SUM A1");
+    // Only a leading character is a problem
+    assert_eq!(name_of("mary@example.com"), "Mary - Shelley");
+}
+
+#[tokio::test]
+async fn confirmed_import_reports_invalid_rows_and_stores_the_valid_ones() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let csv = "email,name\n\
+        ursula@example.com,Ursula Le Guin\n\
+        not-an-email,\n\
+        octavia@example.com,Octavia Butler\n\
+        URSULA@example.com,Ursula again\n\
+        too,many,columns\n";
+
+    // Act
+    let response = test_app
+        .post_admin_subscribers_import("confirmed", csv)
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    let errors = report["errors"].as_array().unwrap();
+    let lines: Vec<_> = errors.iter().map(|e| e["line"].as_u64().unwrap()).collect();
+    assert_eq!(lines, [3, 5, 6]);
+    // Both fields of line 3 are reported
+    assert_eq!(errors[0]["invalid-params"].as_array().unwrap().len(), 2);
+    assert_eq!(errors[1]["invalid-params"][0]["code"], "duplicate");
+    assert_eq!(errors[2]["invalid-params"][0]["code"], "malformed");
+
+    let saved =
+        sqlx::query!("SELECT email, status, consent_source FROM subscriptions ORDER BY email")
+            .fetch_all(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(saved.len(), 2);
+    for subscriber in saved {
+        assert_eq!(subscriber.status, "confirmed");
+        assert_eq!(subscriber.consent_source.as_deref(), Some("import"));
+    }
+}
+
+#[tokio::test]
+async fn pending_import_queues_a_confirmation_email_for_each_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // Two confirmations, then a welcome email once one of them is confirmed
+        .expect(3)
+        .mount(&test_app.email_server)
+        .await;
+    let csv = "name,email\nUrsula Le Guin,ursula@example.com\nOctavia Butler,octavia@example.com\n";
+
+    // Act - Part 1 - Import
+    let response = test_app.post_admin_subscribers_import("pending", csv).await;
+
+    // Assert - Part 1 - Nothing is sent while the admin waits
+    assert_eq!(StatusCode::OK, response.status());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 2);
+    assert!(
+        test_app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .is_empty()
+    );
+
+    // Act - Part 2 - Run the worker
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert - Part 2
+    let statuses = sqlx::query!("SELECT status, consented_at FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    for subscriber in statuses {
+        assert_eq!(subscriber.status, "pending_confirmation");
+        assert!(subscriber.consented_at.is_none());
+    }
+    // Confirmation links work as for the subscription form
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+}
+
+#[tokio::test]
+async fn import_confirmations_do_not_wait_for_pending_issue_deliveries() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let confirmed = "name,email\n\
+        Ursula Le Guin,ursula@example.com\n\
+        Ada Lovelace,ada@example.com\n\
+        Mary Shelley,mary@example.com\n";
+    test_app
+        .post_admin_subscribers_import("confirmed", confirmed)
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app
+        .post_admin_subscribers_import(
+            "pending",
+            "name,email\nOctavia Butler,octavia@example.com\n",
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    test_app.dispatch_next_emails().await;
+
+    // Assert
+    let recipients: Vec<String> = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(recipients.len(), 2);
+    assert!(recipients.contains(&"octavia@example.com".to_owned()));
+    let pending_deliveries =
+        sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(pending_deliveries.count, 2);
+}
+
+#[tokio::test]
+async fn import_never_overrides_an_opt_out() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    let csv = "email,name\nursula@example.com,Ursula Le Guin\n";
+    test_app
+        .post_admin_subscribers_import("confirmed", csv)
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = test_app
+        .post_admin_subscribers_import("confirmed", csv)
+        .await;
+
+    // Assert
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 0);
+    assert_eq!(report["skipped"][0]["line"], 2);
+    assert_eq!(
+        report["skipped"][0]["invalid-params"][0]["code"],
+        "already_unsubscribed"
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn import_rejects_files_it_cannot_read() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+
+    // Act
+    let missing_columns = test_app
+        .post_admin_subscribers_import("confirmed", "mail,full_name\nursula@example.com,Ursula\n")
+        .await;
+    let not_csv = test_app
+        .api_client
+        .post(format!(
+            "{}/admin/subscribers/import?status=confirmed",
+            &test_app.address
+        ))
+        .json(&serde_json::json!([{"email": "ursula@example.com", "name": "Ursula"}]))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, missing_columns.status());
+    assert_eq!(StatusCode::UNSUPPORTED_MEDIA_TYPE, not_csv.status());
+}
+
+#[tokio::test]
+async fn import_takes_files_larger_than_the_default_body_limit() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    // Columns we do not know about are ignored: they make the file large, not the import slow
+    let notes = "x".repeat(1024 * 1024);
+    let csv = format!(
+        "email,name,notes\n\
+        ursula@example.com,Ursula Le Guin,{notes}\n\
+        octavia@example.com,Octavia Butler,{notes}\n\
+        ada@example.com,Ada Lovelace,{notes}\n"
+    );
+    assert!(csv.len() > 2 * 1024 * 1024);
+
+    // Act
+    let response = test_app
+        .post_admin_subscribers_import("confirmed", &csv)
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 3);
+}
+
+#[tokio::test]
+async fn import_rejects_files_over_the_size_limit() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    let csv = format!(
+        "email,name,notes\nursula@example.com,Ursula Le Guin,{}\n",
+        "x".repeat(MAX_IMPORT_SIZE)
+    );
+
+    // Act
+    let response = test_app
+        .post_admin_subscribers_import("confirmed", &csv)
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::PAYLOAD_TOO_LARGE, response.status());
+}
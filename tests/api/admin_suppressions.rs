@@ -0,0 +1,199 @@
+use crate::helpers::{TestApp, assert_is_redirect_to, spawn_app};
+use axum::http::StatusCode;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn suppress(test_app: &TestApp, email: &str) {
+    test_app
+        .post_admin_suppressions(&serde_json::json!({"email": email, "reason": "manual"}))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn suppressed_emails(test_app: &TestApp) -> Vec<String> {
+    let list: serde_json::Value = test_app
+        .get_admin_suppressions()
+        .await
+        .json()
+        .await
+        .unwrap();
+    list["suppressions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_suppressions() {
+    let test_app = spawn_app().await;
+
+    let list = test_app.get_admin_suppressions().await;
+    let add = test_app
+        .post_admin_suppressions(
+            &serde_json::json!({"email": "ursula@example.com", "reason": "manual"}),
+        )
+        .await;
+    let remove = test_app
+        .delete_admin_suppression("ursula@example.com")
+        .await;
+
+    for response in [list, add, remove] {
+        assert_is_redirect_to(&response, "/login");
+    }
+}
+
+#[tokio::test]
+async fn suppressions_can_be_added_listed_and_removed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    let body = serde_json::json!({"email": "Ursula@Example.com", "reason": "erasure_request"});
+
+    // Act - Part 1 - Add, twice
+    let created = test_app.post_admin_suppressions(&body).await;
+    let again = test_app
+        .post_admin_suppressions(
+            &serde_json::json!({"email": "ursula@example.com", "reason": "manual"}),
+        )
+        .await;
+
+    // Assert - Part 1
+    assert_eq!(StatusCode::CREATED, created.status());
+    assert_eq!(StatusCode::OK, again.status());
+    // The first reason sticks
+    let suppression: serde_json::Value = again.json().await.unwrap();
+    assert_eq!(suppression["email"], "ursula@example.com");
+    assert_eq!(suppression["reason"], "erasure_request");
+    assert_eq!(suppression["source"], "admin");
+    assert_eq!(suppressed_emails(&test_app).await, ["ursula@example.com"]);
+
+    // Act - Part 2 - Remove, twice
+    let removed = test_app
+        .delete_admin_suppression("URSULA@example.com")
+        .await;
+    let not_found = test_app
+        .delete_admin_suppression("ursula@example.com")
+        .await;
+
+    // Assert - Part 2
+    assert_eq!(StatusCode::NO_CONTENT, removed.status());
+    assert_eq!(StatusCode::NOT_FOUND, not_found.status());
+    assert!(suppressed_emails(&test_app).await.is_empty());
+}
+
+#[tokio::test]
+async fn invalid_email_addresses_cannot_be_suppressed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+
+    // Act
+    let response = test_app
+        .post_admin_suppressions(&serde_json::json!({"email": "not-an-email", "reason": "manual"}))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["name"], "email");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_get_no_confirmation_email_when_subscribing() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    suppress(&test_app, "ursula_le_guin@gmail.com").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert - The reply does not tell suppressed addresses apart
+    assert_eq!(StatusCode::OK, response.status());
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_subscribers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula@example.com', 'le guin', now(), 'confirmed')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    suppress(&test_app, "ursula@example.com").await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    test_app.dispatch_all_pending_emails().await;
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn suppressed_addresses_are_not_imported() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    suppress(&test_app, "ursula@example.com").await;
+
+    // Act
+    let response = test_app
+        .post_admin_subscribers_import(
+            "confirmed",
+            "email,name\nursula@example.com,Ursula Le Guin\n",
+        )
+        .await;
+
+    // Assert
+    let report: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 0);
+    assert_eq!(
+        report["skipped"][0]["invalid-params"][0]["code"],
+        "suppressed"
+    );
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_subscribers, 0);
+}
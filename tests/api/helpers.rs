@@ -1,12 +1,21 @@
+use secrecy::{ExposeSecret, SecretString};
 use sqlx::{Connection, Executor, PgConnection, PgPool};
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use url::Url;
 use uuid::Uuid;
 use wiremock::MockServer;
 use zero2prod::{
-    configuration::{DatabaseSettings, get_configuration},
+    authentication::compute_password_hash,
+    configuration::{
+        DatabaseSettings, EmailProvider, PostmarkWebhookSettings, Settings, get_configuration,
+    },
+    email_client::EmailClient,
+    issue_delivery_worker::{ExecutionOutcome, UnsubscribeLinks, try_execute_tasks},
+    issue_scheduler::{SchedulingOutcome, try_send_due_issue},
     startup::Application,
+    suppressions::PostgresSuppressionList,
     telemetry::{get_subscriber, init_subscriber},
+    templates::EmailTemplates,
 };
 
 // Ensure that the `tracing` stack is only initialized once since spawn_app is run across multiple tests
@@ -30,6 +39,42 @@ pub struct TestApp {
     pub port: u16,
     pub db_pool: PgPool,
     pub email_server: MockServer,
+    pub test_user: TestUser,
+    pub api_client: reqwest::Client,
+    pub email_client: EmailClient,
+    pub email_templates: EmailTemplates,
+    pub unsubscribe_links: UnsubscribeLinks,
+    pub postmark_webhook: PostmarkWebhookSettings,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, db_pool: &PgPool) {
+        let password_hash = compute_password_hash(SecretString::from(self.password.clone()))
+            .expect("Failed to hash test user password");
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            secrecy::ExposeSecret::expose_secret(&password_hash),
+        )
+        .execute(db_pool)
+        .await
+        .expect("Failed to store test user.");
+    }
 }
 
 pub struct ConfirmationLinks {
@@ -38,9 +83,31 @@ pub struct ConfirmationLinks {
 }
 
 impl TestApp {
+    /// Run the delivery worker in-process until both of its queues are empty.
+    pub async fn dispatch_all_pending_emails(&self) {
+        while let ExecutionOutcome::TaskCompleted = self.dispatch_next_emails().await {}
+    }
+
+    /// Run a single iteration of the delivery worker: at most one task from each queue.
+    pub async fn dispatch_next_emails(&self) -> ExecutionOutcome {
+        try_execute_tasks(
+            &self.db_pool,
+            &self.email_client,
+            &self.email_templates,
+            &self.unsubscribe_links,
+        )
+        .await
+        .unwrap()
+    }
+
+    /// Run the scheduler in-process until no scheduled issue is due.
+    pub async fn send_due_issues(&self) {
+        while let SchedulingOutcome::IssueSent = try_send_due_issue(&self.db_pool).await.unwrap() {}
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
-        reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
             .body(body)
             .send()
@@ -48,6 +115,286 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Accept", "application/json")
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscription_challenge(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/subscriptions/challenge", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/subscriptions/unsubscribe?token={}",
+                &self.address, token
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Mimic a mail client's RFC 8058 one-click unsubscribe request.
+    pub async fn post_unsubscribe(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/subscriptions/unsubscribe?token={}",
+                &self.address, token
+            ))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body("List-Unsubscribe=One-Click")
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Call the webhook the way Postmark does, with the configured credentials.
+    pub async fn post_postmark_webhook(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .basic_auth(
+                &self.postmark_webhook.username,
+                Some(self.postmark_webhook.password.expose_secret()),
+            )
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        self.post_newsletters_with_key(body, &Uuid::new_v4().to_string())
+            .await
+    }
+
+    pub async fn post_newsletters_with_key(
+        &self,
+        body: serde_json::Value,
+        idempotency_key: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .header("Idempotency-Key", idempotency_key)
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(format!("{}/login", &self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_login_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/login", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_dashboard_html(&self) -> String {
+        self.get_admin_dashboard().await.text().await.unwrap()
+    }
+
+    /// `query` is appended as-is: escape it.
+    pub async fn get_admin_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_subscribers_export(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/subscribers/export.csv", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_subscribers_import(
+        &self,
+        status: &str,
+        csv: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/subscribers/import?status={}",
+                &self.address, status
+            ))
+            .header("Content-Type", "text/csv")
+            .body(csv.to_owned())
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_suppressions(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_suppressions(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_suppression(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!("{}/admin/suppressions/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_issues(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin_issue(
+        &self,
+        issue_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_issue_preview(&self, issue_id: &str, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!(
+                "{}/admin/issues/{}/preview?{}",
+                &self.address, issue_id, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_issue_test(
+        &self,
+        issue_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/issues/{}/test", &self.address, issue_id))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_issue_publish(&self, issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/publish",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin_issue_schedule(
+        &self,
+        issue_id: &str,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(format!(
+                "{}/admin/issues/{}/schedule",
+                &self.address, issue_id
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_issue_schedule(&self, issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .delete(format!(
+                "{}/admin/issues/{}/schedule",
+                &self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_logout(&self) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/logout", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn login_test_user(&self) {
+        let login_body = serde_json::json!({
+            "username": &self.test_user.username,
+            "password": &self.test_user.password,
+        });
+        let response = self.post_login(&login_body).await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -65,13 +412,18 @@ impl TestApp {
             confirmation_link
         };
 
-        let html = get_link(&body["HtmlBody"].as_str().unwrap());
-        let plain_text = get_link(&body["TextBody"].as_str().unwrap());
+        let html = get_link(body["HtmlBody"].as_str().unwrap());
+        let plain_text = get_link(body["TextBody"].as_str().unwrap());
         ConfirmationLinks { html, plain_text }
     }
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, letting the test tweak the configuration before the application is built.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     LazyLock::force(&TRACING);
 
     // Launch a mock server to mimic Postmark's API
@@ -85,15 +437,20 @@ pub async fn spawn_app() -> TestApp {
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.host = host.to_string();
         c.application.port = 0;
+        c.email_client.provider = EmailProvider::Postmark;
         c.email_client.base_url = email_server.uri();
         c.email_client.timeout_milliseconds = 250;
+        // Keep retries quick: we do not want tests to sleep through backoffs
+        c.email_client.retry.base_delay_milliseconds = 10;
+        c.email_client.retry.max_delay_milliseconds = 100;
+        configure(&mut c);
         c
     };
 
     let db_pool = configure_database(&config.database).await;
 
     // Build the app
-    let app = Application::build(config)
+    let app = Application::build(config.clone())
         .await
         .expect("The application should build with config");
     let address = format!("http://{}:{}", host, app.port());
@@ -104,12 +461,33 @@ pub async fn spawn_app() -> TestApp {
         app.run().await.expect("server crashed");
     });
 
-    TestApp {
+    // Keep cookies across requests and do not follow redirects, so tests can assert on them
+    let api_client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+
+    let email_client = config
+        .email_client
+        .client(Arc::new(PostgresSuppressionList::new(db_pool.clone())));
+    let test_app = TestApp {
         address,
         port,
         db_pool,
         email_server,
-    }
+        test_user: TestUser::generate(),
+        api_client,
+        email_client,
+        email_templates: config.email_templates.templates().unwrap(),
+        unsubscribe_links: UnsubscribeLinks {
+            base_url: config.application.base_url.clone(),
+            hmac_secret: config.application.hmac_secret.clone(),
+        },
+        postmark_webhook: config.postmark_webhook.clone(),
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
 }
 
 pub async fn configure_database(db_config: &DatabaseSettings) -> PgPool {
@@ -133,3 +511,8 @@ pub async fn configure_database(db_config: &DatabaseSettings) -> PgPool {
 
     connection_pool
 }
+
+pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(response.headers().get("Location").unwrap(), location);
+}
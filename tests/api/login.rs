@@ -0,0 +1,115 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use tower_sessions::ExpiredDeletion;
+use zero2prod::session_store::PostgresSessionStore;
+
+#[tokio::test]
+async fn an_error_flash_message_is_set_on_failure() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act - Part 1 - Try to login
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    let response = test_app.post_login(&login_body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = test_app.get_login_html().await;
+    assert!(html_page.contains("<p><i>Authentication failed.</i></p>"));
+
+    // Act - Part 3 - Reload the login page
+    let html_page = test_app.get_login_html().await;
+    assert!(!html_page.contains("Authentication failed."));
+}
+
+#[tokio::test]
+async fn redirect_to_admin_dashboard_after_login_success() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act - Part 1 - Login
+    let login_body = serde_json::json!({
+        "username": &test_app.test_user.username,
+        "password": &test_app.test_user.password,
+    });
+    let response = test_app.post_login(&login_body).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = test_app.get_admin_dashboard_html().await;
+    assert!(html_page.contains(&format!("Welcome {}", test_app.test_user.username)));
+}
+
+#[tokio::test]
+async fn session_cookie_is_http_only_secure_and_same_site() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let login_body = serde_json::json!({
+        "username": &test_app.test_user.username,
+        "password": &test_app.test_user.password,
+    });
+    let response = test_app.post_login(&login_body).await;
+
+    // Assert
+    let cookie = response
+        .headers()
+        .get("Set-Cookie")
+        .expect("No session cookie was set.")
+        .to_str()
+        .unwrap();
+    assert!(cookie.starts_with("session_id="));
+    assert!(cookie.contains("HttpOnly"));
+    assert!(cookie.contains("Secure"));
+    assert!(cookie.contains("SameSite=Lax"));
+}
+
+#[tokio::test]
+async fn sessions_are_persisted_in_postgres() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    test_app.login_test_user().await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM sessions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to count sessions.");
+    assert_eq!(saved.count, 1);
+}
+
+#[tokio::test]
+async fn expired_sessions_are_deleted() {
+    // Arrange
+    let test_app = spawn_app().await;
+    test_app.login_test_user().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO sessions (id, data, expiry_date)
+        VALUES ('expired', '{}', now() - interval '1 day')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    PostgresSessionStore::new(test_app.db_pool.clone())
+        .delete_expired()
+        .await
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM sessions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to count sessions.");
+    assert_eq!(saved.count, 1);
+}
@@ -1,4 +1,16 @@
+mod admin_dashboard;
+mod admin_issues;
+mod admin_subscribers;
+mod admin_subscribers_csv;
+mod admin_suppressions;
 mod health_check;
 mod helpers;
+mod login;
+mod newsletters;
 mod subscriptions;
+mod subscriptions_bot_protection;
 mod subscriptions_confirm;
+mod subscriptions_locale;
+mod subscriptions_rate_limit;
+mod subscriptions_unsubscribe;
+mod webhooks_postmark;
@@ -0,0 +1,572 @@
+use crate::helpers::{ConfirmationLinks, TestApp, spawn_app};
+use axum::http::StatusCode;
+use secrecy::SecretString;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::configuration::InitialAdminSettings;
+use zero2prod::startup::create_initial_admin;
+
+/// Use the public API of the application under test to create an unconfirmed subscriber.
+async fn create_unconfirmed_subscriber(test_app: &TestApp) -> ConfirmationLinks {
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    // Scoped mock: it only lives until the guard is dropped at the end of this function,
+    // so it does not interfere with the expectations set by the calling test.
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Create unconfirmed subscriber")
+        .expect(1)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app
+        .post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Inspect the requests received by the mock Postmark server to retrieve the confirmation link
+    let email_request = &test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    test_app.get_confirmation_links(email_request)
+}
+
+async fn create_confirmed_subscriber(test_app: &TestApp) {
+    let confirmation_link = create_unconfirmed_subscriber(test_app).await.html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_unconfirmed_subscriber(&test_app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0) // Postmark should never be called
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = test_app.post_newsletters(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    test_app.dispatch_all_pending_emails().await;
+    // Mock verifies on drop that no newsletter was sent
+}
+
+#[tokio::test]
+async fn newsletters_are_delivered_to_confirmed_subscribers() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = test_app.post_newsletters(newsletter_request_body).await;
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    test_app.dispatch_all_pending_emails().await;
+    // Mock verifies on drop that the newsletter was sent
+}
+
+#[tokio::test]
+async fn newsletters_are_wrapped_in_the_newsletter_template() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["HtmlBody"].as_str().unwrap();
+    let text = email["TextBody"].as_str().unwrap();
+    // The issue is HTML already: it is not escaped
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+    assert!(text.starts_with("Newsletter body as plain text"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    assert!(text.contains("/subscriptions/unsubscribe?token="));
+}
+
+#[tokio::test]
+async fn newsletters_written_in_markdown_are_sent_in_both_formats() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let markdown = "Read **the** [post](https://example.com/post).\n\n<script>alert(1)</script>";
+    let response = test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {"markdown": markdown}
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    let email_request = test_app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let email: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = email["HtmlBody"].as_str().unwrap();
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(html.contains(
+        r#"<p>Read <strong>the</strong> <a href="https://example.com/post">post</a>.</p>"#
+    ));
+    assert!(html.contains("&lt;script&gt;"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    assert!(text.starts_with(
+        "Read the post [1].\n\n<script>alert(1)</script>\n\n[1] https://example.com/post"
+    ));
+    let issue = sqlx::query!("SELECT markdown_content FROM newsletter_issues")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.markdown_content.as_deref(), Some(markdown));
+}
+
+#[tokio::test]
+async fn newsletters_returns_422_for_invalid_data() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "content": {
+                    "text": "Newsletter body as plain text",
+                    "html": "<p>Newsletter body as HTML</p>",
+                }
+            }),
+            "missing title",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!"}),
+            "missing content",
+        ),
+        (
+            serde_json::json!({"title": "Newsletter!", "content": {"html": "<p>Hi</p>"}}),
+            "content in neither format",
+        ),
+    ];
+
+    for (invalid_body, error_message) in test_cases {
+        // Act
+        let response = test_app.post_newsletters(invalid_body).await;
+
+        // Assert
+        assert_eq!(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            response.status(),
+            "The API did not fail with 422 Unprocessable Entity when the payload was {error_message}."
+        );
+    }
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_kept_in_the_queue_for_a_later_retry() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1..)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = test_app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        "SELECT n_retries, execute_after > now() AS \"postponed!\" FROM issue_delivery_queue"
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("The failed delivery is no longer queued.");
+    assert_eq!(task.n_retries, 1);
+    assert!(task.postponed);
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_with_invalid_stored_emails_are_skipped() {
+    // Arrange
+    let test_app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'definitely-not-an-email', 'le guin', now(), 'confirmed')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let response = test_app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn requests_missing_authorization_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &test_app.address))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn non_existing_user_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    // Random credentials
+    let username = Uuid::new_v4().to_string();
+    let password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &test_app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn invalid_password_is_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let username = &test_app.test_user.username;
+    // Random password
+    let password = Uuid::new_v4().to_string();
+    assert_ne!(test_app.test_user.password, password);
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &test_app.address))
+        .basic_auth(username, Some(password))
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+    assert_eq!(
+        r#"Basic realm="publish""#,
+        response.headers()["WWW-Authenticate"]
+    );
+}
+
+#[tokio::test]
+async fn requests_missing_an_idempotency_key_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = test_app
+        .api_client
+        .post(format!("{}/newsletters", &test_app.address))
+        .basic_auth(
+            &test_app.test_user.username,
+            Some(&test_app.test_user.password),
+        )
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+}
+
+#[tokio::test]
+async fn newsletter_creation_is_idempotent() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Part 1 - Publish the newsletter
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response = test_app
+        .post_newsletters_with_key(newsletter_request_body.clone(), &idempotency_key)
+        .await;
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+
+    // Act - Part 2 - Retry the same request
+    let response = test_app
+        .post_newsletters_with_key(newsletter_request_body, &idempotency_key)
+        .await;
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn concurrent_newsletter_submissions_are_handled_gracefully() {
+    // Arrange
+    let test_app = spawn_app().await;
+    create_confirmed_subscriber(&test_app).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act - Submit two newsletter requests concurrently
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    let idempotency_key = Uuid::new_v4().to_string();
+    let response1 =
+        test_app.post_newsletters_with_key(newsletter_request_body.clone(), &idempotency_key);
+    let response2 = test_app.post_newsletters_with_key(newsletter_request_body, &idempotency_key);
+    let (response1, response2) = tokio::join!(response1, response2);
+
+    assert_eq!(response1.status(), response2.status());
+    assert_eq!(
+        response1.text().await.unwrap(),
+        response2.text().await.unwrap()
+    );
+
+    test_app.dispatch_all_pending_emails().await;
+
+    // Mock verifies on drop that we have sent the newsletter email **once**
+}
+
+#[tokio::test]
+async fn the_initial_admin_can_publish_on_a_fresh_database() {
+    // Arrange
+    let test_app = spawn_app().await;
+    sqlx::query!("DELETE FROM users")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+    let initial_admin = InitialAdminSettings {
+        username: "founder".into(),
+        password: SecretString::from("correct horse battery staple"),
+    };
+    create_initial_admin(&test_app.db_pool, initial_admin)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::Client::new()
+        .post(format!("{}/newsletters", &test_app.address))
+        .basic_auth("founder", Some("correct horse battery staple"))
+        .header("Idempotency-Key", Uuid::new_v4().to_string())
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+    let n_users = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_users, 1);
+}
+
+#[tokio::test]
+async fn no_admin_is_created_when_there_are_users_already() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let initial_admin = InitialAdminSettings {
+        username: "founder".into(),
+        password: SecretString::from("correct horse battery staple"),
+    };
+
+    // Act
+    create_initial_admin(&test_app.db_pool, initial_admin)
+        .await
+        .unwrap();
+
+    // Assert
+    let n_founders =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM users WHERE username = 'founder'"#)
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_founders, 0);
+}
@@ -74,14 +74,37 @@ async fn subscribe_returns_422_when_data_missing() {
 #[tokio::test]
 async fn subscribe_returns_400_for_present_invalid_fields() {
     let test_app = helpers::spawn_app().await;
+    let too_long_name = format!("name={}&email=ursula_le_guin%40gmail.com", "a".repeat(257));
 
     let test_cases = vec![
-        ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
-        ("name=Ursula&email=", "empty email"),
-        ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        (
+            "name=&email=ursula_le_guin%40gmail.com",
+            ("name", "empty"),
+            "empty name",
+        ),
+        (
+            "name=Ursula%3C%2F%3E&email=ursula_le_guin%40gmail.com",
+            ("name", "forbidden_character"),
+            "name with forbidden characters",
+        ),
+        (
+            too_long_name.as_str(),
+            ("name", "too_long"),
+            "name too long",
+        ),
+        (
+            "name=Ursula&email=",
+            ("email", "invalid_email"),
+            "empty email",
+        ),
+        (
+            "name=Ursula&email=definitely-not-an-email",
+            ("email", "invalid_email"),
+            "invalid email",
+        ),
     ];
 
-    for (body, description) in test_cases {
+    for (body, (field, code), description) in test_cases {
         let response = test_app.post_subscriptions(body.into()).await;
 
         assert_eq!(
@@ -89,9 +112,45 @@ async fn subscribe_returns_400_for_present_invalid_fields() {
             response.status(),
             "The API did not return a 400 Bad Request when the payload was {description}."
         );
+        assert_eq!(
+            response.headers()["Content-Type"],
+            "application/problem+json"
+        );
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["status"], 400);
+        assert_eq!(
+            problem["invalid-params"],
+            serde_json::json!([{
+                "name": field,
+                "code": code,
+                "reason": problem["invalid-params"][0]["reason"],
+            }]),
+            "Unexpected problem details when the payload was {description}."
+        );
     }
 }
 
+#[tokio::test]
+async fn subscribe_reports_every_invalid_field() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=%20&email=definitely-not-an-email";
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    let fields: Vec<_> = problem["invalid-params"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| (p["name"].as_str().unwrap(), p["code"].as_str().unwrap()))
+        .collect();
+    assert_eq!(fields, vec![("name", "empty"), ("email", "invalid_email")]);
+}
+
 #[tokio::test]
 async fn subscribe_sends_confirmation_email_for_valid_data() {
     // Arrange
@@ -137,3 +196,263 @@ async fn subscribe_sends_confirmation_email_with_link() {
     // The two links should be identical
     assert_eq!(html, plain_text);
 }
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_a_working_confirmation() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // Two confirmations, then a welcome email
+        .expect(3)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let first = test_app.post_subscriptions(body.into()).await;
+    let second = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, first.status());
+    assert_eq!(StatusCode::OK, second.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "pending_confirmation");
+
+    // The latest confirmation link works
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[1];
+    let links = test_app.get_confirmation_links(email_request);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_is_a_no_op() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    let mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The confirmation, then a welcome email
+        .expect(2)
+        .mount_as_scoped(&test_app.email_server)
+        .await;
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let links = test_app.get_confirmation_links(email_request);
+    reqwest::get(links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    drop(mock_guard);
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.len(), 1);
+    assert_eq!(saved[0].status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula_le_guin@gmail.com', 'le guin', now(), 'unsubscribed')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_a_bounce_keeps_the_bounce() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula_le_guin@gmail.com', 'le guin', now(), 'bounced')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+}
+
+#[tokio::test]
+async fn subscribe_fails_if_there_is_a_fatal_database_error() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscription_tokens DROP COLUMN subscription_token_hash;",)
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+}
+
+#[tokio::test]
+async fn subscribe_fails_if_the_confirmation_email_cannot_be_sent() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    assert_eq!(StatusCode::INTERNAL_SERVER_ERROR, response.status());
+}
+
+#[tokio::test]
+async fn subscribe_accepts_json_and_replies_with_json() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+    });
+
+    // Act
+    let response = test_app.post_subscriptions_json(&body).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    let reply: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(reply, body);
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn subscribe_returns_400_with_problem_details_for_invalid_json_fields() {
+    // Arrange
+    let test_app = helpers::spawn_app().await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "definitely-not-an-email",
+    });
+
+    // Act
+    let response = test_app.post_subscriptions_json(&body).await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn subscribe_returns_415_for_unsupported_content_types() {
+    let test_app = helpers::spawn_app().await;
+
+    let test_cases = vec![
+        (Some("text/plain"), "plain text"),
+        (Some("multipart/form-data; boundary=x"), "multipart"),
+        (None, "no content type"),
+    ];
+
+    for (content_type, description) in test_cases {
+        let mut request = test_app
+            .api_client
+            .post(format!("{}/subscriptions", &test_app.address))
+            .body("name=le%20guin&email=ursula_le_guin%40gmail.com");
+        if let Some(content_type) = content_type {
+            request = request.header("Content-Type", content_type);
+        }
+
+        let response = request.send().await.unwrap();
+
+        assert_eq!(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            response.status(),
+            "The API did not return a 415 when the payload was {description}."
+        );
+    }
+}
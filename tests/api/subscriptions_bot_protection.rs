@@ -0,0 +1,239 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use axum::http::StatusCode;
+use sha2::{Digest, Sha256};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Brute-force a challenge, like the subscription form does in the browser.
+fn solve(challenge: &str, difficulty: u32) -> String {
+    (0u64..)
+        .map(|n| n.to_string())
+        .find(|solution| {
+            leading_zero_bits(&Sha256::digest(format!("{}:{}", challenge, solution))) >= difficulty
+        })
+        .unwrap()
+}
+
+fn leading_zero_bits(bytes: &[u8]) -> u32 {
+    let zero_bytes = bytes.iter().take_while(|b| **b == 0).count();
+    let next_byte = bytes.get(zero_bytes).map_or(0, |b| b.leading_zeros());
+    zero_bytes as u32 * 8 + next_byte
+}
+
+async fn spawn_app_with_proof_of_work() -> TestApp {
+    spawn_app_with(|c| {
+        c.proof_of_work.enabled = true;
+        // Enough to exercise the check without slowing the test suite down
+        c.proof_of_work.difficulty = 8;
+    })
+    .await
+}
+
+async fn solved_challenge(test_app: &TestApp) -> (String, String) {
+    let response = test_app.get_subscription_challenge().await;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(response.headers()["Cache-Control"], "no-store");
+    let body: serde_json::Value = response.json().await.unwrap();
+    let challenge = body["challenge"].as_str().unwrap().to_owned();
+    let solution = solve(&challenge, body["difficulty"].as_u64().unwrap() as u32);
+    (challenge, solution)
+}
+
+#[tokio::test]
+async fn subscriptions_filling_in_the_honeypot_are_silently_dropped() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=https%3A%2F%2Fspam.example"
+                .into(),
+        )
+        .await;
+
+    // Assert - same reply as a real subscription, but nothing happened
+    assert_eq!(StatusCode::OK, response.status());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions.");
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn an_empty_honeypot_does_not_get_in_the_way() {
+    // Arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com&website=".into())
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+}
+
+#[tokio::test]
+async fn the_challenge_endpoint_is_not_found_when_disabled() {
+    let test_app = spawn_app().await;
+
+    let response = test_app.get_subscription_challenge().await;
+
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+}
+
+#[tokio::test]
+async fn subscribe_with_a_solved_challenge_is_accepted() {
+    // Arrange
+    let test_app = spawn_app_with_proof_of_work().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let (challenge, solution) = solved_challenge(&test_app).await;
+
+    // Act
+    let response = test_app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+            "pow_challenge": challenge,
+            "pow_solution": solution,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn subscribe_without_a_valid_solution_is_rejected_when_enabled() {
+    // Arrange
+    let test_app = spawn_app_with_proof_of_work().await;
+    let (challenge, _) = solved_challenge(&test_app).await;
+    let wrong_solution = (0u64..)
+        .map(|n| n.to_string())
+        .find(|s| leading_zero_bits(&Sha256::digest(format!("{}:{}", challenge, s))) < 8)
+        .unwrap();
+    let test_cases = vec![
+        (serde_json::json!({}), "pow_solution", "missing"),
+        (
+            serde_json::json!({"pow_challenge": "forged", "pow_solution": "0"}),
+            "pow_challenge",
+            "invalid_challenge",
+        ),
+        (
+            serde_json::json!({"pow_challenge": challenge, "pow_solution": wrong_solution}),
+            "pow_solution",
+            "wrong_solution",
+        ),
+    ];
+
+    for (mut body, name, code) in test_cases {
+        body["name"] = "le guin".into();
+        body["email"] = "ursula_le_guin@gmail.com".into();
+
+        // Act
+        let response = test_app.post_subscriptions_json(&body).await;
+
+        // Assert
+        assert_eq!(StatusCode::BAD_REQUEST, response.status());
+        let problem: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(problem["invalid-params"][0]["name"], name);
+        assert_eq!(problem["invalid-params"][0]["code"], code);
+    }
+}
+
+#[tokio::test]
+async fn a_solved_challenge_cannot_be_used_twice() {
+    // Arrange
+    let test_app = spawn_app_with_proof_of_work().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let (challenge, solution) = solved_challenge(&test_app).await;
+    let body = |email: &str| {
+        serde_json::json!({
+            "name": "le guin",
+            "email": email,
+            "pow_challenge": challenge,
+            "pow_solution": solution,
+        })
+    };
+    let response = test_app
+        .post_subscriptions_json(&body("ursula_le_guin@gmail.com"))
+        .await;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // Act
+    let response = test_app
+        .post_subscriptions_json(&body("someone_else@gmail.com"))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["code"], "already_used");
+}
+
+#[tokio::test]
+async fn a_solved_challenge_cannot_be_replayed_against_a_confirmed_address() {
+    // Arrange
+    let test_app = spawn_app_with_proof_of_work().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES (gen_random_uuid(), 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    let (challenge, solution) = solved_challenge(&test_app).await;
+    let body = serde_json::json!({
+        "name": "le guin",
+        "email": "ursula_le_guin@gmail.com",
+        "pow_challenge": challenge,
+        "pow_solution": solution,
+    });
+    let response = test_app.post_subscriptions_json(&body).await;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // Act
+    let response = test_app.post_subscriptions_json(&body).await;
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+    let problem: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(problem["invalid-params"][0]["code"], "already_used");
+}
@@ -1,8 +1,9 @@
 use crate::helpers::{ConfirmationLinks, spawn_app};
 use axum::http::StatusCode;
-use url::Url;
+use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::startup::hash_plaintext_subscription_tokens;
 
 #[tokio::test]
 pub async fn confirmations_without_token_are_rejected_with_400() {
@@ -82,3 +83,234 @@ async fn clicking_confirmation_link_confirms_subscriber() {
     assert_eq!(saved.name, "le guin");
     assert_eq!(saved.status, "confirmed");
 }
+
+#[tokio::test]
+async fn confirmed_subscribers_get_a_welcome_email() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let welcome = &test_app.email_server.received_requests().await.unwrap()[1];
+    let welcome: serde_json::Value = serde_json::from_slice(&welcome.body).unwrap();
+    assert_eq!(welcome["To"], "ursula_le_guin@gmail.com");
+    let html = welcome["HtmlBody"].as_str().unwrap();
+    let text = welcome["TextBody"].as_str().unwrap();
+    assert!(html.contains("le guin") && text.contains("le guin"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    assert!(text.contains("/subscriptions/unsubscribe?token="));
+    assert!(!text.contains("<a "));
+}
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = test_app.get_confirmation_links(email_request).html;
+    reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::GONE, response.status());
+    let token = sqlx::query!("SELECT consumed_at FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(token.consumed_at.is_some());
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_410() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = test_app.get_confirmation_links(email_request).html;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::GONE, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn confirmation_links_do_not_bring_back_subscribers_who_left() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app.post_subscriptions(body.into()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = test_app.get_confirmation_links(email_request).html;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert - no welcome email either
+    assert_eq!(StatusCode::GONE, response.status());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unknown_confirmation_tokens_are_rejected_with_401() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=notarealtoken",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+}
+
+#[tokio::test]
+async fn tokens_are_stored_hashed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    test_app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = test_app.get_confirmation_links(email_request).html;
+    let (_, token) = confirmation_link
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap();
+    let stored = sqlx::query!("SELECT subscription_token_hash FROM subscription_tokens")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.subscription_token_hash, token);
+    assert_eq!(stored.subscription_token_hash.len(), 64);
+}
+
+#[tokio::test]
+async fn plaintext_tokens_from_before_hashing_keep_working() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'pending_confirmation')
+        "#,
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_tokens (
+            subscription_token_hash, subscriber_id, expires_at, is_plaintext
+        )
+        VALUES ('legacyplaintexttoken12345', $1, now() + interval '1 hour', true)
+        "#,
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - what the application does on startup
+    hash_plaintext_subscription_tokens(&test_app.db_pool, &test_app.unsubscribe_links.hmac_secret)
+        .await
+        .unwrap();
+
+    // Assert
+    let stored =
+        sqlx::query!("SELECT subscription_token_hash, is_plaintext FROM subscription_tokens")
+            .fetch_one(&test_app.db_pool)
+            .await
+            .unwrap();
+    assert!(!stored.is_plaintext);
+    assert_ne!(stored.subscription_token_hash, "legacyplaintexttoken12345");
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=legacyplaintexttoken12345",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+    assert_eq!(StatusCode::OK, response.status());
+}
@@ -0,0 +1,121 @@
+use crate::helpers::{TestApp, spawn_app};
+use axum::http::StatusCode;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscribe(test_app: &TestApp, body: &str, accept_language: &str) -> reqwest::Response {
+    test_app
+        .api_client
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept-Language", accept_language)
+        .body(body.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// The locale stored for the only subscriber, and the subject of the only email sent.
+async fn stored_locale_and_subject(test_app: &TestApp) -> (Option<String>, String) {
+    let locale = sqlx::query!("SELECT locale FROM subscriptions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .locale;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    (locale, body["Subject"].as_str().unwrap().to_owned())
+}
+
+async fn mock_email_server(test_app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+}
+
+#[tokio::test]
+async fn the_confirmation_is_written_in_the_preferred_language() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+
+    // Act
+    let response = subscribe(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "it;q=0.9, fr-CH, en;q=0.8",
+    )
+    .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let (locale, subject) = stored_locale_and_subject(&test_app).await;
+    assert_eq!(locale.as_deref(), Some("fr"));
+    assert_eq!(subject, "Bienvenue !");
+    // The confirmation link is still there
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    test_app.get_confirmation_links(email_request);
+}
+
+#[tokio::test]
+async fn an_explicit_locale_takes_precedence_over_accept_language() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+
+    // Act
+    subscribe(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=de",
+        "fr",
+    )
+    .await;
+
+    // Assert
+    let (locale, subject) = stored_locale_and_subject(&test_app).await;
+    assert_eq!(locale.as_deref(), Some("de"));
+    assert_eq!(subject, "Willkommen!");
+}
+
+#[tokio::test]
+async fn unsupported_languages_fall_back_to_the_default_locale() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+
+    // Act
+    subscribe(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&locale=tlh",
+        "pt-BR, ja;q=0.5",
+    )
+    .await;
+
+    // Assert
+    let (locale, subject) = stored_locale_and_subject(&test_app).await;
+    assert_eq!(locale.as_deref(), Some("en"));
+    assert_eq!(subject, "Welcome!");
+}
+
+#[tokio::test]
+async fn refused_languages_are_never_chosen() {
+    // Arrange
+    let test_app = spawn_app().await;
+    mock_email_server(&test_app).await;
+
+    // Act
+    subscribe(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+        "fr;Q=0, ja",
+    )
+    .await;
+
+    // Assert
+    let (locale, subject) = stored_locale_and_subject(&test_app).await;
+    assert_eq!(locale.as_deref(), Some("en"));
+    assert_eq!(subject, "Welcome!");
+}
@@ -0,0 +1,159 @@
+use crate::helpers::spawn_app_with;
+use axum::http::StatusCode;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn too_many_requests_from_the_same_ip_are_rejected_with_429() {
+    // Arrange
+    let test_app = spawn_app_with(|c| {
+        c.rate_limit.per_ip.capacity = 2;
+        c.rate_limit.per_ip.refill_interval_milliseconds = 60_000;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    for i in 0..2 {
+        let body = format!("name=le%20guin&email=ursula_{}%40gmail.com", i);
+        let response = test_app.post_subscriptions(body).await;
+        assert_eq!(StatusCode::OK, response.status());
+    }
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_3%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 60);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "application/problem+json"
+    );
+}
+
+#[tokio::test]
+async fn too_many_requests_for_the_same_email_are_rejected_with_429() {
+    // Arrange
+    let test_app = spawn_app_with(|c| {
+        c.rate_limit.per_email.capacity = 1;
+        c.rate_limit.per_email.refill_interval_milliseconds = 60_000;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // One for the first request, one for the other address
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    test_app
+        .post_subscriptions(body.into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act - same address, different spelling, different content type
+    let response = test_app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "Ursula_Le_Guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+    assert!(response.headers().contains_key("Retry-After"));
+    // Other addresses are unaffected
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=someone_else%40gmail.com".into())
+        .await;
+    assert_eq!(StatusCode::OK, response.status());
+}
+
+#[tokio::test]
+async fn forwarded_for_is_used_when_the_peer_is_a_trusted_proxy() {
+    // Arrange - the test client connects from localhost: pretend it is our proxy
+    let test_app = spawn_app_with(|c| {
+        c.rate_limit.trusted_proxies = vec!["127.0.0.1".parse().unwrap()];
+        c.rate_limit.per_ip.capacity = 1;
+        c.rate_limit.per_ip.refill_interval_milliseconds = 60_000;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let post_from = |client_ip: &'static str, email: &'static str| {
+        test_app
+            .api_client
+            .post(format!("{}/subscriptions", &test_app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", client_ip)
+            .body(format!("name=le%20guin&email={}", email))
+            .send()
+    };
+
+    // Act
+    let first = post_from("198.51.100.1", "ursula_1%40gmail.com")
+        .await
+        .unwrap();
+    let other_client = post_from("198.51.100.2", "ursula_2%40gmail.com")
+        .await
+        .unwrap();
+    let same_client = post_from("198.51.100.1", "ursula_3%40gmail.com")
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::OK, first.status());
+    assert_eq!(StatusCode::OK, other_client.status());
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, same_client.status());
+}
+
+#[tokio::test]
+async fn rejected_requests_do_not_use_up_the_budget_of_an_email() {
+    // Arrange
+    let test_app = spawn_app_with(|c| {
+        c.rate_limit.per_email.capacity = 1;
+        c.rate_limit.per_email.refill_interval_milliseconds = 60_000;
+    })
+    .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    // Junk aimed at someone else's address: an invalid name, then a bot filling in the honeypot
+    let invalid = test_app
+        .post_subscriptions("name=&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(StatusCode::BAD_REQUEST, invalid.status());
+    test_app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&website=spam.example.com".into(),
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+}
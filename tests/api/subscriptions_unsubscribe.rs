@@ -0,0 +1,259 @@
+use crate::helpers::{TestApp, spawn_app, spawn_app_with};
+use axum::http::StatusCode;
+use url::Url;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::domain::UnsubscribeToken;
+
+/// Insert a confirmed subscriber straight into the database and return their id.
+async fn create_confirmed_subscriber(test_app: &TestApp) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'ursula_le_guin@gmail.com', 'le guin', now(), 'confirmed')
+        "#,
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_status(test_app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+fn token_for(test_app: &TestApp, subscriber_id: Uuid) -> String {
+    UnsubscribeToken::generate(subscriber_id, &test_app.unsubscribe_links.hmac_secret)
+        .as_ref()
+        .to_owned()
+}
+
+#[tokio::test]
+async fn unsubscribe_requests_without_token_are_rejected_with_400() {
+    // Arrange
+    let test_app = spawn_app().await;
+
+    // Act
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", test_app.address))
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(StatusCode::BAD_REQUEST, response.status());
+}
+
+#[tokio::test]
+async fn forged_tokens_are_rejected_with_401() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    let mut token = token_for(&test_app, subscriber_id);
+    // Flip the last character of the signature
+    let last = if token.ends_with('A') { "B" } else { "A" };
+    token.replace_range(token.len() - 1.., last);
+
+    // Act
+    let get_response = test_app.get_unsubscribe(&token).await;
+    let post_response = test_app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(StatusCode::UNAUTHORIZED, get_response.status());
+    assert_eq!(StatusCode::UNAUTHORIZED, post_response.status());
+    assert_eq!(
+        "confirmed",
+        subscriber_status(&test_app, subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn visiting_the_unsubscribe_link_asks_for_confirmation_only() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    let token = token_for(&test_app, subscriber_id);
+
+    // Act
+    let response = test_app.get_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"method="post""#));
+    assert_eq!(
+        "confirmed",
+        subscriber_status(&test_app, subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn the_confirmation_form_posts_under_the_base_url() {
+    // Arrange
+    let test_app = spawn_app_with(|c| {
+        c.application.base_url = Url::parse("https://example.com/newsletter/").unwrap();
+    })
+    .await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    let token = token_for(&test_app, subscriber_id);
+
+    // Act
+    let response = test_app.get_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    let html = response.text().await.unwrap();
+    let action = "https://example.com/newsletter/subscriptions/unsubscribe?token=";
+    assert!(html.contains(&format!(
+        r#"action="{}"#,
+        htmlescape::encode_attribute(action)
+    )));
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    let token = token_for(&test_app, subscriber_id);
+
+    // Act
+    let response = test_app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        "unsubscribed",
+        subscriber_status(&test_app, subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn newsletters_carry_a_working_one_click_unsubscribe_header() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    test_app
+        .post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let headers = body["Headers"].as_array().unwrap();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|h| h["Name"] == name)
+            .unwrap()
+            .get("Value")
+            .unwrap()
+            .as_str()
+            .unwrap()
+            .to_owned()
+    };
+    assert_eq!(
+        "List-Unsubscribe=One-Click",
+        header("List-Unsubscribe-Post")
+    );
+    let link = header("List-Unsubscribe");
+    let link = Url::parse(link.trim_start_matches('<').trim_end_matches('>')).unwrap();
+    assert_eq!("/subscriptions/unsubscribe", link.path());
+    let (_, token) = link.query_pairs().find(|(k, _)| k == "token").unwrap();
+
+    let response = test_app.post_unsubscribe(&token).await;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        "unsubscribed",
+        subscriber_status(&test_app, subscriber_id).await
+    );
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_queued_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    });
+    test_app
+        .post_newsletters(newsletter_request_body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Act - unsubscribe after the issue was queued, but before it was delivered
+    let token = token_for(&test_app, subscriber_id);
+    test_app
+        .post_unsubscribe(&token)
+        .await
+        .error_for_status()
+        .unwrap();
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let remaining = sqlx::query!("SELECT COUNT(*) AS count FROM issue_delivery_queue")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(Some(0), remaining.count);
+}
+
+#[tokio::test]
+async fn unsubscribing_keeps_bounces_and_complaints_on_record() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = create_confirmed_subscriber(&test_app).await;
+    sqlx::query!(
+        "UPDATE subscriptions SET status = 'bounced' WHERE id = $1",
+        subscriber_id
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    let token = token_for(&test_app, subscriber_id);
+
+    // Act
+    let response = test_app.post_unsubscribe(&token).await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("bounced", subscriber_status(&test_app, subscriber_id).await);
+}
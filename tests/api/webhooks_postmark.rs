@@ -0,0 +1,254 @@
+use crate::helpers::{TestApp, spawn_app};
+use axum::http::StatusCode;
+use uuid::Uuid;
+
+async fn insert_confirmed_subscriber(test_app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', now(), 'confirmed')
+        "#,
+        subscriber_id,
+        email
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn subscriber_status(test_app: &TestApp, subscriber_id: Uuid) -> String {
+    sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap()
+    .status
+}
+
+/// Trimmed down from Postmark's documentation.
+fn bounce(id: u64, bounce_type: &str, email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "ID": id,
+        "Type": bounce_type,
+        "TypeCode": 1,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found).",
+        "Email": email,
+        "From": "sender@example.com",
+        "BouncedAt": "2026-05-02T16:33:54.9070259Z",
+        "Inactive": bounce_type == "HardBounce",
+        "CanActivate": true,
+        "Subject": "Newsletter",
+        "MessageStream": "outbound",
+    })
+}
+
+fn spam_complaint(id: u64, email: &str) -> serde_json::Value {
+    let mut payload = bounce(id, "SpamComplaint", email);
+    payload["RecordType"] = "SpamComplaint".into();
+    payload["TypeCode"] = 512.into();
+    payload
+}
+
+#[tokio::test]
+async fn webhook_calls_without_valid_credentials_are_rejected() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let url = format!("{}/webhooks/postmark", &test_app.address);
+    let payload = bounce(1, "HardBounce", "ursula@example.com");
+
+    // Act
+    let anonymous = test_app
+        .api_client
+        .post(&url)
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+    let wrong_password = test_app
+        .api_client
+        .post(&url)
+        .basic_auth(&test_app.postmark_webhook.username, Some("guess"))
+        .json(&payload)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    for response in [anonymous, wrong_password] {
+        assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+        assert_eq!(
+            r#"Basic realm="webhooks""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+    let events = sqlx::query!("SELECT id FROM email_events")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert!(events.is_empty());
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced_and_is_recorded() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+
+    // Act
+    let response = test_app
+        .post_postmark_webhook(&bounce(42, "HardBounce", "ursula@example.com"))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(subscriber_status(&test_app, subscriber_id).await, "bounced");
+    let event = sqlx::query!(
+        "SELECT provider_event_id, record_type, bounce_type, subscriber_id, payload FROM email_events"
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(event.provider_event_id, "42");
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.bounce_type.as_deref(), Some("HardBounce"));
+    assert_eq!(event.subscriber_id, Some(subscriber_id));
+    assert_eq!(event.payload["MessageStream"], "outbound");
+    let suppression = sqlx::query!("SELECT email, reason, source FROM suppressions")
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppression.email, "ursula@example.com");
+    assert_eq!(suppression.reason, "hard_bounce");
+    assert_eq!(suppression.source, "postmark");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+
+    // Act
+    let response = test_app
+        .post_postmark_webhook(&spam_complaint(1, "ursula@example.com"))
+        .await;
+    // A later bounce must not hide the complaint
+    test_app
+        .post_postmark_webhook(&bounce(2, "HardBounce", "ursula@example.com"))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        subscriber_status(&test_app, subscriber_id).await,
+        "complained"
+    );
+}
+
+#[tokio::test]
+async fn events_match_the_subscriber_whatever_the_case_of_the_address() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&test_app, "Ursula@Example.com").await;
+
+    // Act
+    let response = test_app
+        .post_postmark_webhook(&bounce(1, "HardBounce", "ursula@EXAMPLE.com"))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(subscriber_status(&test_app, subscriber_id).await, "bounced");
+}
+
+#[tokio::test]
+async fn a_soft_bounce_is_recorded_without_changing_the_subscriber() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+
+    // Act
+    let response = test_app
+        .post_postmark_webhook(&bounce(7, "SoftBounce", "ursula@example.com"))
+        .await;
+
+    // Assert
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        subscriber_status(&test_app, subscriber_id).await,
+        "confirmed"
+    );
+    let n_events = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_events"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_events, 1);
+}
+
+#[tokio::test]
+async fn retried_and_unrelated_events_are_acknowledged() {
+    // Arrange
+    let test_app = spawn_app().await;
+    let payload = bounce(42, "HardBounce", "unknown@example.com");
+
+    // Act
+    let first = test_app.post_postmark_webhook(&payload).await;
+    let retry = test_app.post_postmark_webhook(&payload).await;
+    let delivery = test_app
+        .post_postmark_webhook(&serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+            "Recipient": "ursula@example.com",
+        }))
+        .await;
+
+    // Assert - Postmark retries anything but a 2xx
+    assert_eq!(StatusCode::OK, first.status());
+    assert_eq!(StatusCode::OK, retry.status());
+    assert_eq!(StatusCode::OK, delivery.status());
+    let n_events = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_events"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_events, 1);
+}
+
+#[tokio::test]
+async fn bounced_subscribers_are_not_sent_newsletter_issues() {
+    // Arrange
+    let test_app = spawn_app().await;
+    insert_confirmed_subscriber(&test_app, "ursula@example.com").await;
+    test_app
+        .post_postmark_webhook(&bounce(1, "HardBounce", "ursula@example.com"))
+        .await
+        .error_for_status()
+        .unwrap();
+    wiremock::Mock::given(wiremock::matchers::any())
+        .respond_with(wiremock::ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // Act
+    let response = test_app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    test_app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(StatusCode::ACCEPTED, response.status());
+}